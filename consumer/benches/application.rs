@@ -0,0 +1,31 @@
+//! Static versus dynamic dispatch of the Application services.
+//! Run with `cargo bench -p consumer`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use consumer::{
+    Application, BooleanService, BoxedApplication, Byte, ByteService, DefaultBooleanService,
+    ProviderAdapter,
+};
+
+fn check<B: ByteService, O: BooleanService>(app: &Application<B, O>) -> usize {
+    (0..=u8::MAX)
+        .filter(|byte| {
+            let is_zero = app.byte_service.is_zero(black_box(Byte(*byte))).unwrap();
+            app.boolean_service.is_true(is_zero)
+        })
+        .count()
+}
+
+fn dispatch(c: &mut Criterion) {
+    let generic = Application::new(ProviderAdapter);
+    let boxed: BoxedApplication =
+        Application::with_services(Box::new(ProviderAdapter), Box::new(DefaultBooleanService));
+    let mut group = c.benchmark_group("application");
+    group.bench_function("generic", |b| b.iter(|| check(&generic)));
+    group.bench_function("boxed", |b| b.iter(|| check(&boxed)));
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,511 @@
+//! Validated wiring of an Application and its decorators.
+//!
+//! Decorators wrap the ByteService from the inside out in a fixed order:
+//! retry, circuit breaker, cache, metrics, tracing. Cache hits therefore
+//! skip the breaker and the retries, while metrics and tracing see every call.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::cache::CachedByteService;
+use crate::circuit_breaker::{CircuitBreakerByteService, CircuitBreakerConfig};
+use crate::config::{AdapterKind, ByteServiceConfig, ConfigError};
+use crate::metrics::{MeteredBooleanService, MeteredByteService, Metrics};
+use crate::retry::{RetryPolicy, RetryingByteService};
+use crate::telemetry::TracingByteService;
+use crate::{
+    Application, AsyncApplication, AsyncByteService, BlockingAdapter, Boolean, BooleanService,
+    BoxedApplication, BoxedAsyncApplication, Byte, ByteService, ByteServiceError,
+    DefaultBooleanService, ProviderAdapter,
+};
+
+type SharedByteService = Box<dyn ByteService + Send + Sync>;
+type SharedBooleanService = Box<dyn BooleanService + Send + Sync>;
+
+/// AsyncApplication built by `ApplicationBuilder::build_in_process`, without trait objects.
+pub type InProcessApplication = AsyncApplication<
+    Toggle<TracingByteService<MeteredProvider>, MeteredProvider>,
+    Toggle<MeteredBooleanService<DefaultBooleanService>, DefaultBooleanService>,
+>;
+
+type MeteredProvider = Toggle<MeteredByteService<ProviderAdapter>, ProviderAdapter>;
+
+/// A decorator `D` of the service `S`, applied or not depending on the builder,
+/// without dynamic dispatch.
+pub enum Toggle<D, S> {
+    On(D),
+    Off(S),
+}
+
+#[async_trait]
+impl<D, S> AsyncByteService for Toggle<D, S>
+where
+    D: AsyncByteService + Send + Sync,
+    S: AsyncByteService + Send + Sync,
+{
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        match self {
+            Toggle::On(decorated) => decorated.is_zero(byte).await,
+            Toggle::Off(service) => service.is_zero(byte).await,
+        }
+    }
+}
+
+impl<D: BooleanService, S: BooleanService> BooleanService for Toggle<D, S> {
+    fn is_true(&self, boolean: Boolean) -> bool {
+        match self {
+            Toggle::On(decorated) => decorated.is_true(boolean),
+            Toggle::Off(service) => service.is_true(boolean),
+        }
+    }
+}
+
+/// A reason why the builder cannot build.
+#[derive(Debug)]
+pub enum BuildProblem {
+    /// `component` only makes sense together with `required`.
+    Missing {
+        component: &'static str,
+        required: &'static str,
+    },
+    /// Both components were set, but only one can be used.
+    Conflict(&'static str, &'static str),
+    /// The component was given an unusable setting.
+    Invalid {
+        component: &'static str,
+        reason: String,
+    },
+    /// The configured ByteService cannot be built.
+    Config(ConfigError),
+}
+
+impl fmt::Display for BuildProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildProblem::Missing {
+                component,
+                required,
+            } => write!(f, "{} requires {}", component, required),
+            BuildProblem::Conflict(first, second) => {
+                write!(f, "{} conflicts with {}", first, second)
+            }
+            BuildProblem::Invalid { component, reason } => {
+                write!(f, "invalid {}: {}", component, reason)
+            }
+            BuildProblem::Config(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Every problem found by `ApplicationBuilder::build`, not only the first one.
+#[derive(Debug)]
+pub struct BuildError(pub Vec<BuildProblem>);
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot build the application: ")?;
+        for (index, problem) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Fluent builder of Application and AsyncApplication.
+/// Without any setter, it builds ProviderAdapter and DefaultBooleanService, undecorated.
+#[derive(Default)]
+pub struct ApplicationBuilder {
+    byte_service: Option<SharedByteService>,
+    byte_service_config: Option<ByteServiceConfig>,
+    boolean_service: Option<SharedBooleanService>,
+    name: Option<&'static str>,
+    retry: Option<RetryPolicy>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    fallback: Option<SharedByteService>,
+    cache_capacity: Option<usize>,
+    cache_ttl: Option<Duration>,
+    metrics: Option<Arc<Metrics>>,
+    tracing: bool,
+}
+
+impl ApplicationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the given ByteService.
+    pub fn byte_service(mut self, byte_service: SharedByteService) -> Self {
+        self.byte_service = Some(byte_service);
+        self
+    }
+
+    /// Builds the ByteService from configuration, naming it after its kind.
+    pub fn byte_service_config(mut self, config: ByteServiceConfig) -> Self {
+        self.byte_service_config = Some(config);
+        self
+    }
+
+    pub fn boolean_service(mut self, boolean_service: SharedBooleanService) -> Self {
+        self.boolean_service = Some(boolean_service);
+        self
+    }
+
+    /// Name of the ByteService in metrics and spans.
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// ByteService answering while the circuit breaker is open.
+    pub fn fallback(mut self, fallback: SharedByteService) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    /// Caches up to `capacity` answers.
+    pub fn cache(mut self, capacity: usize) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Expires cached answers after `ttl`.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Records metrics of both services to `metrics`, e.g. `Metrics::global()`.
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn tracing(mut self) -> Self {
+        self.tracing = true;
+        self
+    }
+
+    pub fn build(self) -> Result<BoxedApplication, BuildError> {
+        let (byte_service, boolean_service) = self.assemble()?;
+        Ok(Application::with_services(byte_service, boolean_service))
+    }
+
+    pub fn build_async(self) -> Result<BoxedAsyncApplication, BuildError> {
+        let (byte_service, boolean_service) = self.assemble()?;
+        let byte_service: Arc<dyn ByteService + Send + Sync> = byte_service.into();
+        let byte_service = BlockingAdapter::new(byte_service);
+        Ok(AsyncApplication::with_services(
+            Box::new(byte_service),
+            boolean_service,
+        ))
+    }
+
+    /// Builds the in-process provider with static dispatch: calls run on the caller's
+    /// task, where `build_async` sends each of them to the blocking pool.
+    /// Calls are metered and traced when `metrics` and `tracing` are set.
+    /// Other ByteServices and the resilience decorators need `build_async`.
+    pub fn build_in_process(self) -> Result<InProcessApplication, BuildError> {
+        let mut problems = Vec::new();
+        if let Some(config) = &self.byte_service_config {
+            if config.kind != AdapterKind::Provider {
+                problems.push(BuildProblem::Invalid {
+                    component: "byte_service_config",
+                    reason: format!("the {} adapter is not in process", config.kind.name()),
+                });
+            }
+        }
+        for (component, set) in [
+            ("byte_service", self.byte_service.is_some()),
+            ("boolean_service", self.boolean_service.is_some()),
+            ("retry", self.retry.is_some()),
+            ("circuit_breaker", self.circuit_breaker.is_some()),
+            ("fallback", self.fallback.is_some()),
+            (
+                "cache",
+                self.cache_capacity.is_some() || self.cache_ttl.is_some(),
+            ),
+        ] {
+            if set {
+                problems.push(BuildProblem::Invalid {
+                    component,
+                    reason: "not supported in process".into(),
+                });
+            }
+        }
+        if !problems.is_empty() {
+            return Err(BuildError(problems));
+        }
+
+        let name = self.name.unwrap_or(AdapterKind::Provider.name());
+        let (byte_service, boolean_service) = match self.metrics {
+            Some(metrics) => (
+                Toggle::On(
+                    MeteredByteService::named(ProviderAdapter, name)
+                        .with_metrics(Arc::clone(&metrics)),
+                ),
+                Toggle::On(
+                    MeteredBooleanService::named(DefaultBooleanService, "default")
+                        .with_metrics(metrics),
+                ),
+            ),
+            None => (
+                Toggle::Off(ProviderAdapter),
+                Toggle::Off(DefaultBooleanService),
+            ),
+        };
+        let byte_service = match self.tracing {
+            true => Toggle::On(TracingByteService::named(byte_service, name)),
+            false => Toggle::Off(byte_service),
+        };
+        Ok(AsyncApplication::with_services(
+            byte_service,
+            boolean_service,
+        ))
+    }
+
+    fn assemble(self) -> Result<(SharedByteService, SharedBooleanService), BuildError> {
+        let mut problems = Vec::new();
+        if self.byte_service.is_some() && self.byte_service_config.is_some() {
+            problems.push(BuildProblem::Conflict(
+                "byte_service",
+                "byte_service_config",
+            ));
+        }
+        if self.cache_ttl.is_some() && self.cache_capacity.is_none() {
+            problems.push(BuildProblem::Missing {
+                component: "cache_ttl",
+                required: "cache",
+            });
+        }
+        if self.cache_capacity == Some(0) {
+            problems.push(BuildProblem::Invalid {
+                component: "cache",
+                reason: "capacity must be positive".into(),
+            });
+        }
+        if self.fallback.is_some() && self.circuit_breaker.is_none() {
+            problems.push(BuildProblem::Missing {
+                component: "fallback",
+                required: "circuit_breaker",
+            });
+        }
+        let config_name = self
+            .byte_service_config
+            .as_ref()
+            .map(|config| config.kind.name());
+        let byte_service: Option<SharedByteService> =
+            match (self.byte_service, self.byte_service_config) {
+                (Some(byte_service), _) => Some(byte_service),
+                (None, Some(config)) => match config.build() {
+                    Ok(byte_service) => Some(byte_service),
+                    Err(error) => {
+                        problems.push(BuildProblem::Config(error));
+                        None
+                    }
+                },
+                (None, None) => Some(Box::new(ProviderAdapter)),
+            };
+        let mut byte_service: SharedByteService = match byte_service {
+            Some(byte_service) if problems.is_empty() => byte_service,
+            _ => return Err(BuildError(problems)),
+        };
+
+        let name = self.name.or(config_name).unwrap_or("custom");
+        if let Some(policy) = self.retry {
+            byte_service = Box::new(RetryingByteService::new(byte_service, policy));
+        }
+        if let Some(config) = self.circuit_breaker {
+            let mut breaker = CircuitBreakerByteService::new(byte_service, config);
+            if let Some(fallback) = self.fallback {
+                breaker = breaker.with_fallback(fallback);
+            }
+            byte_service = Box::new(breaker);
+        }
+        if let Some(capacity) = self.cache_capacity {
+            let mut cache = CachedByteService::new(byte_service, capacity);
+            if let Some(ttl) = self.cache_ttl {
+                cache = cache.with_ttl(ttl);
+            }
+            byte_service = Box::new(cache);
+        }
+        let (mut boolean_service, boolean_name): (SharedBooleanService, _) =
+            match self.boolean_service {
+                Some(boolean_service) => (boolean_service, "custom"),
+                None => (Box::new(DefaultBooleanService), "default"),
+            };
+        if let Some(metrics) = self.metrics {
+            byte_service = Box::new(
+                MeteredByteService::named(byte_service, name).with_metrics(Arc::clone(&metrics)),
+            );
+            boolean_service = Box::new(
+                MeteredBooleanService::named(boolean_service, boolean_name).with_metrics(metrics),
+            );
+        }
+        if self.tracing {
+            byte_service = Box::new(TracingByteService::named(byte_service, name));
+        }
+        Ok((byte_service, boolean_service))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::predicate::Predicate;
+    use crate::{AsyncByteService, Boolean, Byte, MockByteService};
+
+    #[test]
+    fn builds_defaults() {
+        let app = ApplicationBuilder::new().build().unwrap();
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert!(app.boolean_service.is_true(Boolean(true)));
+    }
+
+    /// Decorators wrap the given service: the cache keeps repeated calls from the mock,
+    /// and metrics are recorded for both services, the ByteService under the given name.
+    #[test]
+    fn wires_decorators() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        let metrics = Arc::new(Metrics::new());
+        let app = ApplicationBuilder::new()
+            .byte_service(Box::new(mock))
+            .name("mock")
+            .retry(RetryPolicy::default())
+            .cache(4)
+            .metrics(Arc::clone(&metrics))
+            .tracing()
+            .build()
+            .unwrap();
+        assert_eq!(app.byte_service.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(app.byte_service.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(metrics.calls("byte_service", "is_zero", "mock", true), 2);
+        assert!(!app.boolean_service.is_true(Boolean(false)));
+        assert_eq!(
+            metrics.calls("boolean_service", "is_true", "default", true),
+            1
+        );
+    }
+
+    /// Predicates reach the inner service through every decorator.
+    #[test]
+    fn forwards_predicates() {
+        let mut mock = MockByteService::new();
+        mock.expect_evaluate()
+            .with(
+                mockall::predicate::eq(Byte(3)),
+                mockall::predicate::eq(Predicate::Odd),
+            )
+            .times(1)
+            .returning(|_, _| Ok(Boolean(true)));
+        let app = ApplicationBuilder::new()
+            .byte_service(Box::new(mock))
+            .retry(RetryPolicy::default())
+            .circuit_breaker(CircuitBreakerConfig::default())
+            .cache(4)
+            .metrics(Arc::new(Metrics::new()))
+            .tracing()
+            .build()
+            .unwrap();
+        assert_eq!(
+            app.byte_service.evaluate(Byte(3), &Predicate::Odd),
+            Ok(Boolean(true))
+        );
+    }
+
+    /// All problems are reported at once.
+    #[test]
+    fn lists_every_problem() {
+        let stub = ByteServiceConfig {
+            kind: AdapterKind::Stub,
+            ..Default::default()
+        };
+        let error = ApplicationBuilder::new()
+            .byte_service(Box::new(ProviderAdapter))
+            .byte_service_config(stub.clone())
+            .cache_ttl(Duration::from_secs(1))
+            .fallback(Box::new(ProviderAdapter))
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "cannot build the application: byte_service conflicts with byte_service_config; \
+             cache_ttl requires cache; fallback requires circuit_breaker"
+        );
+
+        let error = ApplicationBuilder::new()
+            .byte_service_config(stub)
+            .cache(0)
+            .build_async()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.0.as_slice(),
+            [
+                BuildProblem::Invalid {
+                    component: "cache",
+                    ..
+                },
+                BuildProblem::Config(ConfigError::MissingSetting(AdapterKind::Stub, "is_zero")),
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn builds_in_process() {
+        let metrics = Arc::new(Metrics::new());
+        let app = ApplicationBuilder::new()
+            .byte_service_config(ByteServiceConfig::default())
+            .metrics(Arc::clone(&metrics))
+            .build_in_process()
+            .unwrap();
+        let is_zero = AsyncByteService::is_zero(&app.byte_service, Byte(0)).await;
+        assert_eq!(is_zero, Ok(Boolean(true)));
+        assert!(app.boolean_service.is_true(Boolean(true)));
+        assert_eq!(
+            metrics.calls("byte_service", "is_zero", "provider", true),
+            1
+        );
+        assert!(matches!(app.byte_service, Toggle::Off(Toggle::On(_))));
+
+        let app = ApplicationBuilder::new().build_in_process().unwrap();
+        assert!(matches!(app.byte_service, Toggle::Off(Toggle::Off(_))));
+        assert!(matches!(app.boolean_service, Toggle::Off(_)));
+
+        let error = ApplicationBuilder::new()
+            .byte_service_config(ByteServiceConfig {
+                kind: AdapterKind::Stub,
+                ..Default::default()
+            })
+            .cache(4)
+            .build_in_process()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "cannot build the application: invalid byte_service_config: \
+             the stub adapter is not in process; invalid cache: not supported in process"
+        );
+    }
+}
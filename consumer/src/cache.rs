@@ -0,0 +1,304 @@
+//! Memoizing decorator for any ByteService.
+//! `is_zero` is a pure function of the byte, so answers can be reused
+//! instead of calling the (possibly expensive) inner service again.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+
+/// Hit and miss counters of a CachedByteService.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry {
+    is_zero: bool,
+    inserted_at: Instant,
+    last_used: u64,
+}
+
+/// Least recently used entries, evicted beyond `capacity`.
+/// With at most 256 distinct keys, finding the oldest entry by scanning
+/// is cheap enough that no linked list is needed.
+struct Lru {
+    entries: HashMap<Byte, Entry>,
+    clock: u64,
+}
+
+/// Decorator caching the answers of the inner ByteService.
+/// Errors are never cached.
+pub struct CachedByteService<S> {
+    inner: S,
+    capacity: usize,
+    ttl: Option<Duration>,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: ByteService> CachedByteService<S> {
+    /// Caches up to `capacity` answers, for as long as they are used.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity: capacity.max(1),
+            ttl: None,
+            lru: Mutex::new(Lru {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Answers older than `ttl` are fetched again.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forgets the answer for one byte.
+    pub fn invalidate(&self, byte: Byte) {
+        self.lock().entries.remove(&byte);
+    }
+
+    /// Forgets every answer.
+    pub fn invalidate_all(&self) {
+        self.lock().entries.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.lru.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn get(&self, lru: &mut Lru, byte: Byte) -> Option<bool> {
+        let expired = match (lru.entries.get(&byte), self.ttl) {
+            (None, _) => return None,
+            (Some(entry), Some(ttl)) => entry.inserted_at.elapsed() >= ttl,
+            (Some(_), None) => false,
+        };
+        if expired {
+            lru.entries.remove(&byte);
+            return None;
+        }
+        lru.clock += 1;
+        let entry = lru.entries.get_mut(&byte)?;
+        entry.last_used = lru.clock;
+        Some(entry.is_zero)
+    }
+
+    fn insert(&self, lru: &mut Lru, byte: Byte, is_zero: bool) {
+        if lru.entries.len() >= self.capacity && !lru.entries.contains_key(&byte) {
+            let oldest = lru
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(byte, _)| *byte);
+            if let Some(oldest) = oldest {
+                lru.entries.remove(&oldest);
+            }
+        }
+        lru.clock += 1;
+        let entry = Entry {
+            is_zero,
+            inserted_at: Instant::now(),
+            last_used: lru.clock,
+        };
+        lru.entries.insert(byte, entry);
+    }
+
+    fn count(&self, hits: usize, misses: usize) {
+        self.hits.fetch_add(hits as u64, Ordering::Relaxed);
+        self.misses.fetch_add(misses as u64, Ordering::Relaxed);
+    }
+}
+
+impl<S: ByteService> ByteService for CachedByteService<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        if let Some(is_zero) = self.get(&mut self.lock(), byte) {
+            self.count(1, 0);
+            return Ok(Boolean(is_zero));
+        }
+        self.count(0, 1);
+        // The lock is not held while calling the inner service.
+        let boolean = self.inner.is_zero(byte)?;
+        self.insert(&mut self.lock(), byte, boolean.0);
+        Ok(boolean)
+    }
+
+    /// Only the missing bytes reach the inner service, each once, in a single batch.
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let cached: Vec<_> = {
+            let mut lru = self.lock();
+            bytes.iter().map(|byte| self.get(&mut lru, *byte)).collect()
+        };
+        // Index in `missing` of each byte to fetch.
+        let mut positions = HashMap::new();
+        let mut missing = Vec::new();
+        for (byte, cached) in bytes.iter().zip(&cached) {
+            if cached.is_none() {
+                positions.entry(*byte).or_insert_with(|| {
+                    missing.push(*byte);
+                    missing.len() - 1
+                });
+            }
+        }
+        self.count(bytes.len() - missing.len(), missing.len());
+        let fetched = match missing.is_empty() {
+            true => Vec::new(),
+            false => self.inner.is_zero_many(&missing)?,
+        };
+        if fetched.len() < missing.len() {
+            return Err(ByteServiceError::Internal(
+                "inner service answered too few bytes".into(),
+            ));
+        }
+        let mut lru = self.lock();
+        for (byte, boolean) in missing.iter().zip(&fetched) {
+            self.insert(&mut lru, *byte, boolean.0);
+        }
+        Ok(bytes
+            .iter()
+            .zip(cached)
+            .map(|(byte, cached)| match cached {
+                Some(is_zero) => Boolean(is_zero),
+                None => Boolean(fetched[positions[byte]].0),
+            })
+            .collect())
+    }
+
+    /// Bytes are served from the cache, wider values always reach the inner service.
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        match value {
+            Value::Byte(byte) => self.is_zero(*byte),
+            value => self.inner.is_zero_value(value),
+        }
+    }
+
+    /// Only is_zero answers are cached.
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        self.inner.evaluate(byte, predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockByteService;
+
+    /// Repeated inputs never reach the inner service.
+    #[test]
+    fn serves_repeated_inputs_from_cache() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        mock.expect_is_zero_many()
+            .withf(|bytes| bytes == [Byte(1)])
+            .times(1)
+            .returning(|_| Ok(vec![Boolean(false)]));
+        let cache = CachedByteService::new(mock, 8);
+        assert_eq!(cache.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(cache.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(
+            cache.is_zero_many(&[Byte(0), Byte(1), Byte(0)]),
+            Ok(vec![Boolean(true), Boolean(false), Boolean(true)])
+        );
+        assert_eq!(cache.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(cache.stats(), CacheStats { hits: 4, misses: 2 });
+    }
+
+    /// A batch repeating a byte fetches it once.
+    #[test]
+    fn fetches_repeated_bytes_once() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_many()
+            .withf(|bytes| bytes == [Byte(5), Byte(0)])
+            .times(1)
+            .returning(|_| Ok(vec![Boolean(false), Boolean(true)]));
+        let cache = CachedByteService::new(mock, 8);
+        assert_eq!(
+            cache.is_zero_many(&[Byte(5), Byte(5), Byte(0), Byte(5)]),
+            Ok(vec![
+                Boolean(false),
+                Boolean(false),
+                Boolean(true),
+                Boolean(false)
+            ])
+        );
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 2 });
+    }
+
+    /// Least recently used, invalidated and expired answers are fetched again.
+    #[test]
+    fn evicts_invalidates_and_expires() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(3)
+            .returning(|_| Ok(Boolean(true)));
+        mock.expect_is_zero()
+            .with(mockall::predicate::in_iter([Byte(1), Byte(2)]))
+            .times(3)
+            .returning(|_| Ok(Boolean(false)));
+        let cache = CachedByteService::new(mock, 2).with_ttl(Duration::from_millis(20));
+        cache.is_zero(Byte(0)).unwrap();
+        cache.is_zero(Byte(1)).unwrap();
+        cache.is_zero(Byte(0)).unwrap();
+        // Evicts 1, the least recently used.
+        cache.is_zero(Byte(2)).unwrap();
+        cache.is_zero(Byte(1)).unwrap();
+        cache.invalidate(Byte(0));
+        cache.is_zero(Byte(0)).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        cache.is_zero(Byte(0)).unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 6 });
+    }
+
+    #[test]
+    fn passes_values_through() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_value()
+            .with(mockall::predicate::eq(Value::I64(0)))
+            .times(2)
+            .returning(|_| Ok(Boolean(true)));
+        mock.expect_is_zero()
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        let cache = CachedByteService::new(mock, 8);
+        for _ in 0..2 {
+            assert_eq!(cache.is_zero_value(&Value::I64(0)), Ok(Boolean(true)));
+            assert_eq!(
+                cache.is_zero_value(&Value::Byte(Byte(1))),
+                Ok(Boolean(false))
+            );
+        }
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[test]
+    fn does_not_cache_errors() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .times(2)
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        let cache = CachedByteService::new(mock, 8);
+        assert!(cache.is_zero(Byte(0)).is_err());
+        assert!(cache.is_zero(Byte(0)).is_err());
+    }
+}
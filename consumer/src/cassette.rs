@@ -0,0 +1,278 @@
+//! Record and replay of ByteService traffic.
+//! A cassette is a JSON lines file, one `{"byte":0,"is_zero":true}` object
+//! per answered byte: the same shape the binary prints with `--format json`.
+//! Wider values are recorded as the provider payload they are sent as,
+//! e.g. `{"value":{"type":"u16","value":256},"is_zero":false}`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use provider::Payload;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, ProviderAdapter, Value};
+
+/// One line of a cassette.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Byte { byte: u8, is_zero: bool },
+    Value { value: Payload, is_zero: bool },
+}
+
+impl Entry {
+    fn new(value: &Value, boolean: &Boolean) -> Self {
+        match value {
+            Value::Byte(byte) => Entry::Byte {
+                byte: byte.0,
+                is_zero: boolean.0,
+            },
+            value => Entry::Value {
+                value: ProviderAdapter::convert_value(value),
+                is_zero: boolean.0,
+            },
+        }
+    }
+}
+
+/// Decorator recording every answer of the inner ByteService to a cassette.
+/// Errors are passed through and not recorded, so a replay
+/// of the cassette fails loudly on the inputs that failed.
+pub struct RecordingByteService<S, W: Write = BufWriter<File>> {
+    inner: S,
+    writer: Mutex<W>,
+}
+
+impl<S: ByteService> RecordingByteService<S> {
+    /// Records to a new cassette file, truncating any existing one.
+    pub fn create(inner: S, path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(inner, BufWriter::new(file)))
+    }
+}
+
+impl<S: ByteService, W: Write> RecordingByteService<S, W> {
+    pub fn new(inner: S, writer: W) -> Self {
+        Self {
+            inner,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Gives back the inner service and the cassette writer.
+    pub fn into_parts(self) -> (S, W) {
+        let writer = self
+            .writer
+            .into_inner()
+            .unwrap_or_else(|error| error.into_inner());
+        (self.inner, writer)
+    }
+
+    fn record(&self, entries: &[Entry]) -> Result<(), ByteServiceError> {
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut write = || -> io::Result<()> {
+            for entry in entries {
+                serde_json::to_writer(&mut *writer, entry)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()
+        };
+        write().map_err(|error| {
+            ByteServiceError::Internal(format!("cannot write cassette: {}", error))
+        })
+    }
+}
+
+impl<S: ByteService, W: Write> ByteService for RecordingByteService<S, W> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let boolean = self.inner.is_zero(byte)?;
+        self.record(&[Entry::new(&Value::Byte(byte), &boolean)])?;
+        Ok(boolean)
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let booleans = self.inner.is_zero_many(bytes)?;
+        let entries: Vec<_> = bytes
+            .iter()
+            .zip(&booleans)
+            .map(|(byte, boolean)| Entry::new(&Value::Byte(*byte), boolean))
+            .collect();
+        self.record(&entries)?;
+        Ok(booleans)
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let boolean = self.inner.is_zero_value(value)?;
+        self.record(&[Entry::new(value, &boolean)])?;
+        Ok(boolean)
+    }
+
+    /// Passed through without being recorded, as replays cannot answer predicates.
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        self.inner.evaluate(byte, predicate)
+    }
+}
+
+/// ByteService answering from a cassette, without any provider.
+/// Inputs missing from the cassette are errors, never guesses.
+pub struct ReplayByteService {
+    answers: HashMap<u8, bool>,
+    values: HashMap<Payload, bool>,
+}
+
+impl ReplayByteService {
+    /// Loads a cassette file.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a cassette. Blank lines are skipped; malformed lines and
+    /// bytes recorded with different answers are rejected.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut answers = HashMap::new();
+        let mut values = HashMap::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("cassette line {}: {}", index + 1, message),
+                )
+            };
+            let entry: Entry =
+                serde_json::from_str(&line).map_err(|error| invalid(error.to_string()))?;
+            let conflicting = match entry {
+                Entry::Byte { byte, is_zero } => answers
+                    .insert(byte, is_zero)
+                    .filter(|previous| *previous != is_zero)
+                    .map(|_| format!("byte {}", byte)),
+                Entry::Value { value, is_zero } => values
+                    .insert(value.clone(), is_zero)
+                    .filter(|previous| *previous != is_zero)
+                    .map(|_| format!("value {:?}", value)),
+            };
+            if let Some(input) = conflicting {
+                return Err(invalid(format!("conflicting answers for {}", input)));
+            }
+        }
+        Ok(Self { answers, values })
+    }
+}
+
+impl ByteService for ReplayByteService {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        match self.answers.get(&byte.0) {
+            Some(is_zero) => Ok(Boolean(*is_zero)),
+            None => Err(ByteServiceError::InvalidInput(format!(
+                "byte {} was not recorded in the cassette",
+                byte.0
+            ))),
+        }
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        if let Value::Byte(byte) = value {
+            return self.is_zero(*byte);
+        }
+        match self.values.get(&ProviderAdapter::convert_value(value)) {
+            Some(is_zero) => Ok(Boolean(*is_zero)),
+            None => Err(ByteServiceError::InvalidInput(format!(
+                "value {:?} was not recorded in the cassette",
+                value
+            ))),
+        }
+    }
+
+    /// Cassettes do not record predicates.
+    fn evaluate(&self, _byte: Byte, _predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        Err(ByteServiceError::Internal("unsupported".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockByteService;
+
+    /// Traffic recorded from the real adapter is served back by the replay,
+    /// which rejects anything it has not seen.
+    #[test]
+    fn replays_recorded_traffic() {
+        let recorder = RecordingByteService::new(ProviderAdapter, Vec::new());
+        assert_eq!(recorder.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(
+            recorder.is_zero_many(&[Byte(1), Byte(2)]),
+            Ok(vec![Boolean(false), Boolean(false)])
+        );
+        let (_, cassette) = recorder.into_parts();
+        assert_eq!(
+            String::from_utf8(cassette.clone()).unwrap(),
+            "{\"byte\":0,\"is_zero\":true}\n\
+             {\"byte\":1,\"is_zero\":false}\n\
+             {\"byte\":2,\"is_zero\":false}\n"
+        );
+
+        let replay = ReplayByteService::from_reader(cassette.as_slice()).unwrap();
+        assert_eq!(replay.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(replay.is_zero(Byte(2)), Ok(Boolean(false)));
+        assert!(matches!(
+            replay.is_zero(Byte(3)),
+            Err(ByteServiceError::InvalidInput(_))
+        ));
+    }
+
+    /// Value calls reach the inner service as such, and are replayed too.
+    #[test]
+    fn records_value_calls() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_value()
+            .with(mockall::predicate::eq(Value::U16(256)))
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        mock.expect_is_zero_value()
+            .with(mockall::predicate::eq(Value::Byte(Byte(0))))
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        let recorder = RecordingByteService::new(mock, Vec::new());
+        assert_eq!(recorder.is_zero_value(&Value::U16(256)), Ok(Boolean(false)));
+        assert_eq!(
+            recorder.is_zero_value(&Value::Byte(Byte(0))),
+            Ok(Boolean(true))
+        );
+        let (_, cassette) = recorder.into_parts();
+        assert_eq!(
+            String::from_utf8(cassette.clone()).unwrap(),
+            "{\"value\":{\"type\":\"u16\",\"value\":256},\"is_zero\":false}\n\
+             {\"byte\":0,\"is_zero\":true}\n"
+        );
+
+        let replay = ReplayByteService::from_reader(cassette.as_slice()).unwrap();
+        assert_eq!(replay.is_zero_value(&Value::U16(256)), Ok(Boolean(false)));
+        assert_eq!(replay.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert!(matches!(
+            replay.is_zero_value(&Value::U32(256)),
+            Err(ByteServiceError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_cassettes() {
+        let conflicting = "{\"byte\":1,\"is_zero\":false}\n{\"byte\":1,\"is_zero\":true}\n";
+        let error = ReplayByteService::from_reader(conflicting.as_bytes())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(ReplayByteService::from_reader("not json".as_bytes()).is_err());
+    }
+}
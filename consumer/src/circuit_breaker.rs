@@ -0,0 +1,396 @@
+//! Circuit breaker decorator for any ByteService.
+//! While the inner service keeps failing, calls stop reaching it
+//! and are answered by an optional fallback instead.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+
+/// State of the circuit.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CircuitState {
+    /// Calls reach the inner service, outcomes are tracked.
+    Closed,
+    /// Calls are rejected (or sent to the fallback) until the cool-down ends.
+    Open,
+    /// A single probe call reaches the inner service to decide whether to close.
+    HalfOpen,
+}
+
+/// When the circuit opens and for how long.
+#[derive(Clone)]
+pub struct CircuitBreakerConfig {
+    window_size: usize,
+    minimum_calls: usize,
+    failure_rate: f64,
+    cool_down: Duration,
+    is_failure: fn(&ByteServiceError) -> bool,
+}
+
+impl Default for CircuitBreakerConfig {
+    /// Opens when half of the last 20 calls failed (after at least 10 calls),
+    /// for 30 seconds. Rejected inputs are the caller's fault and do not count.
+    fn default() -> Self {
+        Self {
+            window_size: 20,
+            minimum_calls: 10,
+            failure_rate: 0.5,
+            cool_down: Duration::from_secs(30),
+            is_failure: |error| !matches!(error, ByteServiceError::InvalidInput(_)),
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Number of most recent calls the failure rate is computed over.
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size.max(1);
+        self.minimum_calls = self.minimum_calls.min(self.window_size);
+        self
+    }
+
+    /// The circuit does not open before this many calls are in the window.
+    pub fn minimum_calls(mut self, minimum_calls: usize) -> Self {
+        self.minimum_calls = minimum_calls.clamp(1, self.window_size);
+        self
+    }
+
+    /// Fraction (0 to 1) of failed calls in the window that opens the circuit.
+    pub fn failure_rate(mut self, failure_rate: f64) -> Self {
+        self.failure_rate = failure_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How long the circuit stays open before probing the inner service.
+    pub fn cool_down(mut self, cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self
+    }
+
+    /// Which errors count as failures of the inner service.
+    pub fn is_failure(mut self, is_failure: fn(&ByteServiceError) -> bool) -> Self {
+        self.is_failure = is_failure;
+        self
+    }
+}
+
+/// Mutable part of the breaker.
+struct Circuit {
+    state: CircuitState,
+    /// Outcomes of the latest calls, `true` for failures.
+    window: VecDeque<bool>,
+    opened_at: Instant,
+    probing: bool,
+}
+
+type TransitionObserver = Box<dyn Fn(CircuitState, CircuitState) + Send + Sync>;
+
+/// Decorator protecting callers (and the inner service) from a degraded inner service.
+pub struct CircuitBreakerByteService<S> {
+    inner: S,
+    fallback: Option<Box<dyn ByteService + Send + Sync>>,
+    config: CircuitBreakerConfig,
+    circuit: Mutex<Circuit>,
+    observer: Option<TransitionObserver>,
+}
+
+impl<S: ByteService> CircuitBreakerByteService<S> {
+    pub fn new(inner: S, config: CircuitBreakerConfig) -> Self {
+        Self {
+            inner,
+            fallback: None,
+            circuit: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                window: VecDeque::with_capacity(config.window_size),
+                opened_at: Instant::now(),
+                probing: false,
+            }),
+            config,
+            observer: None,
+        }
+    }
+
+    /// Service answering while the circuit is open.
+    /// Without one, calls fail with `ByteServiceError::Unavailable`.
+    pub fn with_fallback(mut self, fallback: Box<dyn ByteService + Send + Sync>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    /// Called with the old and new state on every transition.
+    pub fn on_transition<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(CircuitState, CircuitState) + Send + Sync + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Current state, as of the latest call.
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Circuit> {
+        self.circuit
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn call<T>(
+        &self,
+        call: impl Fn(&dyn ByteService) -> Result<T, ByteServiceError>,
+    ) -> Result<T, ByteServiceError> {
+        if !self.permit() {
+            return match &self.fallback {
+                Some(fallback) => call(fallback.as_ref()),
+                None => Err(ByteServiceError::Unavailable),
+            };
+        }
+        let reaching = Reaching(&self.circuit);
+        let result = call(&self.inner);
+        drop(reaching);
+        let failed = matches!(&result, Err(error) if (self.config.is_failure)(error));
+        self.record(failed);
+        result
+    }
+
+    /// Whether a call may reach the inner service.
+    fn permit(&self) -> bool {
+        let mut circuit = self.lock();
+        let transition = match circuit.state {
+            CircuitState::Closed => return true,
+            CircuitState::HalfOpen if circuit.probing => return false,
+            CircuitState::HalfOpen => None,
+            CircuitState::Open if circuit.opened_at.elapsed() < self.config.cool_down => {
+                return false;
+            }
+            CircuitState::Open => Some(self.transition(&mut circuit, CircuitState::HalfOpen)),
+        };
+        circuit.probing = true;
+        drop(circuit);
+        self.notify(transition);
+        true
+    }
+
+    /// Tracks the outcome of a call that reached the inner service.
+    fn record(&self, failed: bool) {
+        let mut circuit = self.lock();
+        let transition = match circuit.state {
+            CircuitState::HalfOpen => {
+                circuit.probing = false;
+                let state = match failed {
+                    true => CircuitState::Open,
+                    false => CircuitState::Closed,
+                };
+                Some(self.transition(&mut circuit, state))
+            }
+            // A call let through before the circuit opened: its outcome is stale.
+            CircuitState::Open => None,
+            CircuitState::Closed => {
+                if circuit.window.len() == self.config.window_size {
+                    circuit.window.pop_front();
+                }
+                circuit.window.push_back(failed);
+                let calls = circuit.window.len();
+                let failures = circuit.window.iter().filter(|failed| **failed).count();
+                let tripped = calls >= self.config.minimum_calls
+                    && failures > 0
+                    && failures as f64 >= self.config.failure_rate * calls as f64;
+                tripped.then(|| self.transition(&mut circuit, CircuitState::Open))
+            }
+        };
+        drop(circuit);
+        self.notify(transition);
+    }
+
+    fn transition(&self, circuit: &mut Circuit, to: CircuitState) -> (CircuitState, CircuitState) {
+        let from = circuit.state;
+        circuit.state = to;
+        circuit.window.clear();
+        if to == CircuitState::Open {
+            circuit.opened_at = Instant::now();
+        }
+        (from, to)
+    }
+
+    /// Observers are called without holding the lock, so they may query the state.
+    fn notify(&self, transition: Option<(CircuitState, CircuitState)>) {
+        if let (Some(observer), Some((from, to))) = (&self.observer, transition) {
+            observer(from, to);
+        }
+    }
+}
+
+/// Guards a call reaching the inner service: if it panics, a probe is no longer
+/// in flight, and the next call probes again instead of being rejected forever.
+struct Reaching<'a>(&'a Mutex<Circuit>);
+
+impl Drop for Reaching<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let mut circuit = self.0.lock().unwrap_or_else(|error| error.into_inner());
+            circuit.probing = false;
+        }
+    }
+}
+
+impl<S: ByteService> ByteService for CircuitBreakerByteService<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.call(|service| service.is_zero(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        self.call(|service| service.is_zero_many(bytes))
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        self.call(|service| service.is_zero_value(value))
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        self.call(|service| service.evaluate(byte, predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockByteService, StubByteService};
+    use std::sync::Arc;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig::default()
+            .window_size(4)
+            .minimum_calls(2)
+            .failure_rate(0.5)
+            .cool_down(Duration::from_millis(20))
+    }
+
+    /// The circuit goes through every state, and the inner service
+    /// is not called while it is open.
+    #[test]
+    fn opens_probes_and_closes() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(1)))
+            .times(2)
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let observed = Arc::clone(&transitions);
+        let breaker = CircuitBreakerByteService::new(mock, config())
+            .on_transition(move |from, to| observed.lock().unwrap().push((from, to)));
+
+        assert_eq!(breaker.is_zero(Byte(1)), Err(ByteServiceError::Unavailable));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.is_zero(Byte(1)), Err(ByteServiceError::Unavailable));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        // Rejected without reaching the mock.
+        assert_eq!(breaker.is_zero(Byte(0)), Err(ByteServiceError::Unavailable));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(breaker.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec![
+                (CircuitState::Closed, CircuitState::Open),
+                (CircuitState::Open, CircuitState::HalfOpen),
+                (CircuitState::HalfOpen, CircuitState::Closed),
+            ]
+        );
+    }
+
+    /// While open, the fallback answers; a failed probe opens the circuit again.
+    #[test]
+    fn uses_fallback_while_open() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .times(3)
+            .returning(|_| Err(ByteServiceError::Timeout));
+        let breaker = CircuitBreakerByteService::new(mock, config())
+            .with_fallback(Box::new(StubByteService::new(false)));
+
+        assert_eq!(breaker.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+        assert_eq!(breaker.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+        assert_eq!(breaker.is_zero(Byte(0)), Ok(Boolean(false)));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(breaker.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.is_zero(Byte(0)), Ok(Boolean(false)));
+    }
+
+    /// Wider values count like any other call.
+    #[test]
+    fn protects_value_calls() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_value()
+            .with(mockall::predicate::eq(Value::U32(1)))
+            .times(2)
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        let breaker = CircuitBreakerByteService::new(mock, config());
+        for _ in 0..3 {
+            assert_eq!(
+                breaker.is_zero_value(&Value::U32(1)),
+                Err(ByteServiceError::Unavailable)
+            );
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    /// Fails twice, panics once, then answers.
+    struct Panicking(std::sync::atomic::AtomicUsize);
+
+    impl ByteService for Panicking {
+        fn is_zero(&self, _: Byte) -> Result<Boolean, ByteServiceError> {
+            match self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 | 1 => Err(ByteServiceError::Unavailable),
+                2 => panic!("probe panicked"),
+                _ => Ok(Boolean(true)),
+            }
+        }
+
+        fn evaluate(&self, _: Byte, _: &Predicate) -> Result<Boolean, ByteServiceError> {
+            unreachable!()
+        }
+    }
+
+    /// A probe that panics does not keep the circuit half open forever.
+    #[test]
+    fn probes_again_after_a_panic() {
+        let breaker = CircuitBreakerByteService::new(Panicking(Default::default()), config());
+        assert!(breaker.is_zero(Byte(0)).is_err());
+        assert!(breaker.is_zero(Byte(0)).is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(30));
+        let probe =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| breaker.is_zero(Byte(0))));
+        assert!(probe.is_err());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(breaker.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    /// Invalid inputs are not the inner service's fault.
+    #[test]
+    fn ignores_invalid_inputs() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .times(4)
+            .returning(|_| Err(ByteServiceError::InvalidInput("nope".into())));
+        let breaker = CircuitBreakerByteService::new(mock, config());
+        for _ in 0..4 {
+            assert!(breaker.is_zero(Byte(0)).is_err());
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
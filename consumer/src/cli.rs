@@ -0,0 +1,362 @@
+//! Command line interface of the consumer binary.
+//! Parsing and rendering live here so they can be unit tested
+//! without spawning the binary.
+
+use std::fmt;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::{AsyncApplication, AsyncByteService, BooleanService, Byte};
+
+/// Exit code when some byte is not zero.
+pub const EXIT_NOT_ZERO: u8 = 1;
+/// Exit code on usage or service errors.
+pub const EXIT_ERROR: u8 = 2;
+
+/// Usage string printed on `--help` and on invalid arguments.
+pub const USAGE: &str = "\
+Usage: consumer [--format plain|json] [--config PATH] [--metrics] [--metrics-listen ADDR] [BYTE...]
+
+Checks that every BYTE (0-255) is zero.
+When no BYTE is given, whitespace separated values are read from stdin,
+which must be a pipe or a file holding at least one.
+
+Options:
+  -f, --format <FORMAT>  Output format: plain (default) or json (JSON lines)
+  -c, --config <PATH>    Configuration file (TOML, or JSON if ending in .json)
+      --metrics          Print Prometheus metrics to stderr before exiting
+      --metrics-listen <ADDR>
+                         Serve Prometheus metrics on http://ADDR/metrics, and keep
+                         serving them after the check until interrupted
+  -h, --help             Print this message";
+
+/// How each result is printed on stdout.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Plain,
+    JsonLines,
+}
+
+/// Where the bytes to check come from.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Input {
+    Args(Vec<Byte>),
+    Stdin,
+}
+
+/// Options of a check.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Options {
+    pub input: Input,
+    pub format: OutputFormat,
+    pub config: Option<PathBuf>,
+    pub metrics_dump: bool,
+    pub metrics_listen: Option<SocketAddr>,
+}
+
+/// What the user asked for.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Command {
+    Help,
+    Check(Options),
+}
+
+/// Errors due to invalid user input.
+#[derive(PartialEq, Eq, Debug)]
+pub enum CliError {
+    InvalidByte(String),
+    InvalidFormat(String),
+    InvalidAddress(String),
+    MissingValue(String),
+    UnknownOption(String),
+    /// Neither the arguments nor stdin hold a byte.
+    NoBytes,
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::InvalidByte(value) => write!(f, "invalid byte '{}', expected 0-255", value),
+            CliError::InvalidFormat(value) => {
+                write!(f, "invalid format '{}', expected plain or json", value)
+            }
+            CliError::InvalidAddress(value) => {
+                write!(f, "invalid address '{}', expected IP:PORT", value)
+            }
+            CliError::MissingValue(option) => write!(f, "missing value for {}", option),
+            CliError::UnknownOption(option) => write!(f, "unknown option {}", option),
+            CliError::NoBytes => write!(f, "no byte to check"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Parses the arguments, excluding the program name.
+pub fn parse_args<I>(args: I) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut format = OutputFormat::Plain;
+    let mut config = None;
+    let mut metrics_dump = false;
+    let mut metrics_listen = None;
+    let mut bytes = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-f" | "--format" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg))?;
+                format = parse_format(&value)?;
+            }
+            "-c" | "--config" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg))?;
+                config = Some(value.into());
+            }
+            "--metrics" => metrics_dump = true,
+            "--metrics-listen" => {
+                let value = args.next().ok_or(CliError::MissingValue(arg))?;
+                metrics_listen = Some(parse_address(&value)?);
+            }
+            _ if arg.starts_with("--metrics-listen=") => {
+                metrics_listen = Some(parse_address(&arg["--metrics-listen=".len()..])?);
+            }
+            _ if arg.starts_with("--config=") => {
+                config = Some(arg["--config=".len()..].into());
+            }
+            _ if arg.starts_with("--format=") => {
+                format = parse_format(&arg["--format=".len()..])?;
+            }
+            // Negative numbers are bytes out of range, not options.
+            _ if arg.starts_with('-') && arg[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                return Err(CliError::InvalidByte(arg));
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CliError::UnknownOption(arg));
+            }
+            _ => bytes.push(parse_byte(&arg)?),
+        }
+    }
+    let input = match bytes.is_empty() {
+        true => Input::Stdin,
+        false => Input::Args(bytes),
+    };
+    Ok(Command::Check(Options {
+        input,
+        format,
+        config,
+        metrics_dump,
+        metrics_listen,
+    }))
+}
+
+/// Parses whitespace separated bytes, as read from stdin, of which there must be one.
+pub fn parse_bytes(text: &str) -> Result<Vec<Byte>, CliError> {
+    let bytes: Vec<Byte> = text
+        .split_whitespace()
+        .map(parse_byte)
+        .collect::<Result<_, _>>()?;
+    match bytes.is_empty() {
+        true => Err(CliError::NoBytes),
+        false => Ok(bytes),
+    }
+}
+
+fn parse_byte(value: &str) -> Result<Byte, CliError> {
+    value
+        .parse()
+        .map(Byte)
+        .map_err(|_| CliError::InvalidByte(value.to_string()))
+}
+
+fn parse_address(value: &str) -> Result<SocketAddr, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError::InvalidAddress(value.to_string()))
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, CliError> {
+    match value {
+        "plain" => Ok(OutputFormat::Plain),
+        "json" | "jsonl" => Ok(OutputFormat::JsonLines),
+        _ => Err(CliError::InvalidFormat(value.to_string())),
+    }
+}
+
+/// Renders the result for a single byte as one output line.
+pub fn render(format: OutputFormat, byte: Byte, is_zero: bool) -> String {
+    match format {
+        OutputFormat::Plain => format!("{}: {}", byte.0, is_zero),
+        OutputFormat::JsonLines => format!("{{\"byte\":{},\"is_zero\":{}}}", byte.0, is_zero),
+    }
+}
+
+/// Checks every byte, printing results to `out` and failures to `err`.
+/// Returns the exit code of the binary.
+pub async fn check<B: AsyncByteService, O: BooleanService>(
+    app: &AsyncApplication<B, O>,
+    bytes: Vec<Byte>,
+    format: OutputFormat,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> u8 {
+    // Nothing sensible can be done if the terminal itself fails.
+    let mut failures = 0;
+    for byte in bytes {
+        let is_zero = match app.byte_service.is_zero(byte).await {
+            Ok(is_zero) => is_zero,
+            Err(error) => {
+                let _ = writeln!(err, "Whoops: {}.", error);
+                return EXIT_ERROR;
+            }
+        };
+        let is_zero = app.boolean_service.is_true(is_zero);
+        let _ = writeln!(out, "{}", render(format, byte, is_zero));
+        if !is_zero {
+            failures += 1;
+        }
+    }
+    match failures {
+        0 => {
+            if format == OutputFormat::Plain {
+                let _ = writeln!(out, "All good.");
+            }
+            0
+        }
+        _ => {
+            let _ = writeln!(err, "Whoops: {} byte(s) are not zero.", failures);
+            EXIT_NOT_ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boolean, DefaultBooleanService, MockAsyncByteService, MockBooleanService};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_bytes_and_format() {
+        assert_eq!(
+            parse_args(args(&["--format", "json", "0", "-c", "app.toml", "255"])),
+            Ok(Command::Check(Options {
+                input: Input::Args(vec![Byte(0), Byte(255)]),
+                format: OutputFormat::JsonLines,
+                config: Some("app.toml".into()),
+                metrics_dump: false,
+                metrics_listen: None,
+            }))
+        );
+        assert_eq!(
+            parse_args(args(&["--metrics", "--metrics-listen", "127.0.0.1:9898"])),
+            Ok(Command::Check(Options {
+                input: Input::Stdin,
+                format: OutputFormat::Plain,
+                config: None,
+                metrics_dump: true,
+                metrics_listen: Some("127.0.0.1:9898".parse().unwrap()),
+            }))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(
+            parse_args(args(&["256"])),
+            Err(CliError::InvalidByte("256".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["-f", "xml"])),
+            Err(CliError::InvalidFormat("xml".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["--format"])),
+            Err(CliError::MissingValue("--format".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["--metrics-listen", "localhost"])),
+            Err(CliError::InvalidAddress("localhost".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["-x"])),
+            Err(CliError::UnknownOption("-x".into()))
+        );
+        assert_eq!(
+            parse_args(args(&["-1"])),
+            Err(CliError::InvalidByte("-1".into()))
+        );
+        assert_eq!(parse_bytes("0 1\n2"), Ok(vec![Byte(0), Byte(1), Byte(2)]));
+        assert_eq!(parse_bytes(" \n"), Err(CliError::NoBytes));
+    }
+
+    #[test]
+    fn renders_each_format() {
+        assert_eq!(render(OutputFormat::Plain, Byte(3), false), "3: false");
+        assert_eq!(
+            render(OutputFormat::JsonLines, Byte(0), true),
+            r#"{"byte":0,"is_zero":true}"#
+        );
+    }
+
+    /// Mocking the BooleanService drives the failure branch,
+    /// whatever the ByteService answers.
+    #[tokio::test]
+    async fn check_fails_when_not_true() {
+        let mut byte_service = MockAsyncByteService::new();
+        byte_service
+            .expect_is_zero()
+            .returning(|_| Ok(Boolean(true)));
+        let mut boolean_service = MockBooleanService::new();
+        boolean_service
+            .expect_is_true()
+            .with(mockall::predicate::eq(Boolean(true)))
+            .times(2)
+            .returning(|_| false);
+        let app = AsyncApplication::with_services(byte_service, boolean_service);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = check(
+            &app,
+            vec![Byte(0), Byte(0)],
+            OutputFormat::Plain,
+            &mut out,
+            &mut err,
+        )
+        .await;
+        assert_eq!(code, EXIT_NOT_ZERO);
+        assert_eq!(String::from_utf8(out).unwrap(), "0: false\n0: false\n");
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            "Whoops: 2 byte(s) are not zero.\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn check_succeeds_when_all_true() {
+        let mut byte_service = MockAsyncByteService::new();
+        byte_service
+            .expect_is_zero()
+            .returning(|_| Ok(Boolean(true)));
+        let app = AsyncApplication::with_services(byte_service, DefaultBooleanService);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = check(
+            &app,
+            vec![Byte(0)],
+            OutputFormat::JsonLines,
+            &mut out,
+            &mut err,
+        )
+        .await;
+        assert_eq!(code, 0);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"byte\":0,\"is_zero\":true}\n"
+        );
+        assert!(err.is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+//! Runtime selection of the ByteService implementation.
+//! The configuration is read from a TOML or JSON file, then overridden
+//! by environment variables, so backends can be switched without recompiling.
+//!
+//! ```toml
+//! [byte_service]
+//! kind = "stub"    # provider (default), remote, tcp, uds, subprocess, stub or replay
+//! is_zero = true   # stub only
+//! # url = "http://localhost:8080"   # remote only
+//! # address = "localhost:9090"      # tcp only
+//! # socket = "/run/provider.sock"   # uds only
+//! # command = ["provider-cli", "--json"]   # subprocess only
+//! # timeout_ms = 5000               # remote, tcp, uds and subprocess only, optional
+//! # connect_timeout_ms = 1000       # remote and tcp only, optional
+//! # cassette = "cassette.jsonl"     # replay only
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::cassette::ReplayByteService;
+use crate::http::HttpProviderAdapter;
+use crate::subprocess::SubprocessByteService;
+use crate::tcp::TcpProviderAdapter;
+#[cfg(unix)]
+use crate::uds::UdsProviderAdapter;
+use crate::{ByteService, ProviderAdapter, StubByteService};
+
+/// Environment variable holding the path of the configuration file.
+pub const CONFIG_ENV: &str = "CONSUMER_CONFIG";
+/// Environment variables overriding the matching `byte_service` settings.
+pub const KIND_ENV: &str = "CONSUMER_BYTE_SERVICE";
+pub const URL_ENV: &str = "CONSUMER_REMOTE_URL";
+pub const ADDRESS_ENV: &str = "CONSUMER_REMOTE_ADDRESS";
+pub const SOCKET_ENV: &str = "CONSUMER_REMOTE_SOCKET";
+/// The program and its arguments, separated by whitespace.
+pub const COMMAND_ENV: &str = "CONSUMER_SUBPROCESS_COMMAND";
+pub const TIMEOUT_ENV: &str = "CONSUMER_REMOTE_TIMEOUT_MS";
+pub const CONNECT_TIMEOUT_ENV: &str = "CONSUMER_REMOTE_CONNECT_TIMEOUT_MS";
+pub const IS_ZERO_ENV: &str = "CONSUMER_STUB_IS_ZERO";
+pub const CASSETTE_ENV: &str = "CONSUMER_REPLAY_CASSETTE";
+
+/// Which ByteService implementation to build.
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AdapterKind {
+    /// The in-process provider, through ProviderAdapter.
+    #[default]
+    Provider,
+    /// A provider running in another process, through HttpProviderAdapter.
+    Remote,
+    /// A provider running in another process, through TcpProviderAdapter.
+    Tcp,
+    /// A provider running next to this process, through UdsProviderAdapter.
+    /// Unix only.
+    Uds,
+    /// A provider executable, through SubprocessByteService.
+    Subprocess,
+    /// A fixed answer, whatever the input.
+    Stub,
+    /// Answers served from a recorded cassette.
+    Replay,
+}
+
+impl AdapterKind {
+    /// Name of the kind, as written in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdapterKind::Provider => "provider",
+            AdapterKind::Remote => "remote",
+            AdapterKind::Tcp => "tcp",
+            AdapterKind::Uds => "uds",
+            AdapterKind::Subprocess => "subprocess",
+            AdapterKind::Stub => "stub",
+            AdapterKind::Replay => "replay",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "provider" => Some(AdapterKind::Provider),
+            "remote" => Some(AdapterKind::Remote),
+            "tcp" => Some(AdapterKind::Tcp),
+            "uds" => Some(AdapterKind::Uds),
+            "subprocess" => Some(AdapterKind::Subprocess),
+            "stub" => Some(AdapterKind::Stub),
+            "replay" => Some(AdapterKind::Replay),
+            _ => None,
+        }
+    }
+}
+
+/// Settings of the ByteService.
+/// Fields are optional so that any of them can come from the environment;
+/// `build` checks that the selected kind has what it needs.
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ByteServiceConfig {
+    pub kind: AdapterKind,
+    pub url: Option<String>,
+    pub address: Option<String>,
+    pub socket: Option<PathBuf>,
+    pub command: Option<Vec<String>>,
+    pub timeout_ms: Option<u64>,
+    pub connect_timeout_ms: Option<u64>,
+    pub is_zero: Option<bool>,
+    pub cassette: Option<PathBuf>,
+}
+
+/// Whole configuration file.
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub byte_service: ByteServiceConfig,
+}
+
+/// Errors while loading the configuration or building services from it.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    InvalidEnv(&'static str, String),
+    MissingSetting(AdapterKind, &'static str),
+    Unsupported(AdapterKind),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, error) => write!(f, "cannot read {}: {}", path.display(), error),
+            ConfigError::Parse(path, error) => {
+                write!(f, "cannot parse {}: {}", path.display(), error)
+            }
+            ConfigError::InvalidEnv(name, value) => write!(f, "invalid {}: '{}'", name, value),
+            ConfigError::MissingSetting(kind, setting) => {
+                write!(
+                    f,
+                    "byte_service.{} is required by the {} adapter",
+                    setting,
+                    kind.name()
+                )
+            }
+            ConfigError::Unsupported(kind) => {
+                write!(
+                    f,
+                    "the {} adapter is not available in this build",
+                    kind.name()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads a configuration file. Files ending in `.json` are parsed as JSON,
+    /// anything else as TOML.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.into(), error))?;
+        let parsed = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(|error| error.to_string()),
+            _ => toml::from_str(&text).map_err(|error| error.to_string()),
+        };
+        parsed.map_err(|error| ConfigError::Parse(path.into(), error))
+    }
+
+    /// Loads the configuration the binary runs with: the given file (or the one
+    /// named by `CONSUMER_CONFIG`, or the defaults), overridden by the environment.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let env_path = std::env::var_os(CONFIG_ENV).map(PathBuf::from);
+        let mut config = match path.or(env_path.as_deref()) {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Overrides settings with the variables found by `lookup`.
+    /// Takes a lookup function rather than reading the environment,
+    /// so tests need not mutate the process environment.
+    pub fn apply_env<F>(&mut self, lookup: F) -> Result<(), ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let settings = &mut self.byte_service;
+        if let Some(value) = lookup(KIND_ENV) {
+            settings.kind =
+                AdapterKind::parse(&value).ok_or(ConfigError::InvalidEnv(KIND_ENV, value))?;
+        }
+        if let Some(value) = lookup(URL_ENV) {
+            settings.url = Some(value);
+        }
+        if let Some(value) = lookup(ADDRESS_ENV) {
+            settings.address = Some(value);
+        }
+        if let Some(value) = lookup(SOCKET_ENV) {
+            settings.socket = Some(value.into());
+        }
+        if let Some(value) = lookup(COMMAND_ENV) {
+            settings.command = Some(value.split_whitespace().map(String::from).collect());
+        }
+        if let Some(value) = lookup(TIMEOUT_ENV) {
+            let timeout = value
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv(TIMEOUT_ENV, value))?;
+            settings.timeout_ms = Some(timeout);
+        }
+        if let Some(value) = lookup(CONNECT_TIMEOUT_ENV) {
+            let timeout = value
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv(CONNECT_TIMEOUT_ENV, value))?;
+            settings.connect_timeout_ms = Some(timeout);
+        }
+        if let Some(value) = lookup(IS_ZERO_ENV) {
+            let is_zero = value
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv(IS_ZERO_ENV, value))?;
+            settings.is_zero = Some(is_zero);
+        }
+        if let Some(value) = lookup(CASSETTE_ENV) {
+            settings.cassette = Some(value.into());
+        }
+        Ok(())
+    }
+}
+
+impl ByteServiceConfig {
+    /// Builds the selected ByteService.
+    pub fn build(&self) -> Result<Box<dyn ByteService + Send + Sync>, ConfigError> {
+        match self.kind {
+            AdapterKind::Provider => Ok(Box::new(ProviderAdapter)),
+            AdapterKind::Stub => {
+                let is_zero = self
+                    .is_zero
+                    .ok_or(ConfigError::MissingSetting(self.kind, "is_zero"))?;
+                Ok(Box::new(StubByteService::new(is_zero)))
+            }
+            AdapterKind::Replay => {
+                let cassette = self
+                    .cassette
+                    .as_ref()
+                    .ok_or(ConfigError::MissingSetting(self.kind, "cassette"))?;
+                let replay = ReplayByteService::open(cassette)
+                    .map_err(|error| ConfigError::Io(cassette.clone(), error))?;
+                Ok(Box::new(replay))
+            }
+            AdapterKind::Remote => {
+                let url = self
+                    .url
+                    .as_ref()
+                    .ok_or(ConfigError::MissingSetting(self.kind, "url"))?;
+                let connect_timeout = self.connect_timeout_ms.map_or(
+                    HttpProviderAdapter::DEFAULT_CONNECT_TIMEOUT,
+                    Duration::from_millis,
+                );
+                let timeout = self
+                    .timeout_ms
+                    .map_or(HttpProviderAdapter::DEFAULT_TIMEOUT, Duration::from_millis);
+                Ok(Box::new(HttpProviderAdapter::with_timeouts(
+                    url,
+                    connect_timeout,
+                    timeout,
+                )))
+            }
+            AdapterKind::Tcp => {
+                let address = self
+                    .address
+                    .as_ref()
+                    .ok_or(ConfigError::MissingSetting(self.kind, "address"))?;
+                let connect_timeout = self.connect_timeout_ms.map_or(
+                    TcpProviderAdapter::DEFAULT_CONNECT_TIMEOUT,
+                    Duration::from_millis,
+                );
+                let timeout = self
+                    .timeout_ms
+                    .map_or(TcpProviderAdapter::DEFAULT_TIMEOUT, Duration::from_millis);
+                Ok(Box::new(TcpProviderAdapter::with_timeouts(
+                    address,
+                    connect_timeout,
+                    timeout,
+                )))
+            }
+            #[cfg(unix)]
+            AdapterKind::Uds => {
+                let socket = self
+                    .socket
+                    .as_ref()
+                    .ok_or(ConfigError::MissingSetting(self.kind, "socket"))?;
+                let timeout = self
+                    .timeout_ms
+                    .map_or(UdsProviderAdapter::DEFAULT_TIMEOUT, Duration::from_millis);
+                Ok(Box::new(UdsProviderAdapter::with_timeout(socket, timeout)))
+            }
+            #[cfg(not(unix))]
+            AdapterKind::Uds => Err(ConfigError::Unsupported(self.kind)),
+            AdapterKind::Subprocess => {
+                let (program, args) = self
+                    .command
+                    .as_deref()
+                    .and_then(<[String]>::split_first)
+                    .ok_or(ConfigError::MissingSetting(self.kind, "command"))?;
+                let timeout = self
+                    .timeout_ms
+                    .map_or(SubprocessByteService::DEFAULT_TIMEOUT, Duration::from_millis);
+                Ok(Box::new(
+                    SubprocessByteService::new(program)
+                        .with_args(args)
+                        .with_timeout(timeout),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boolean, Byte};
+
+    #[test]
+    fn parses_toml_and_json_files() {
+        let toml: Config =
+            toml::from_str("[byte_service]\nkind = \"stub\"\nis_zero = false\n").unwrap();
+        let json: Config =
+            serde_json::from_str(r#"{"byte_service": {"kind": "stub", "is_zero": false}}"#)
+                .unwrap();
+        assert_eq!(toml, json);
+        assert_eq!(toml.byte_service.kind, AdapterKind::Stub);
+        assert_eq!(toml.byte_service.is_zero, Some(false));
+        assert_eq!(Config::default().byte_service.kind, AdapterKind::Provider);
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let mut config: Config = toml::from_str("[byte_service]\nkind = \"provider\"\n").unwrap();
+        config
+            .apply_env(|name| match name {
+                KIND_ENV => Some("stub".into()),
+                IS_ZERO_ENV => Some("true".into()),
+                _ => None,
+            })
+            .unwrap();
+        let byte_service = config.byte_service.build().unwrap();
+        assert_eq!(byte_service.is_zero(Byte(1)), Ok(Boolean(true)));
+
+        let invalid = config.apply_env(|name| (name == KIND_ENV).then(|| "ftp".into()));
+        assert!(matches!(invalid, Err(ConfigError::InvalidEnv(KIND_ENV, _))));
+    }
+
+    #[test]
+    fn build_checks_required_settings() {
+        let stub = ByteServiceConfig {
+            kind: AdapterKind::Stub,
+            ..Default::default()
+        };
+        assert!(matches!(
+            stub.build(),
+            Err(ConfigError::MissingSetting(AdapterKind::Stub, "is_zero"))
+        ));
+        let replay = ByteServiceConfig {
+            kind: AdapterKind::Replay,
+            cassette: Some("does/not/exist.jsonl".into()),
+            ..Default::default()
+        };
+        assert!(matches!(replay.build(), Err(ConfigError::Io(_, _))));
+        let remote = ByteServiceConfig {
+            kind: AdapterKind::Remote,
+            timeout_ms: Some(100),
+            ..Default::default()
+        };
+        assert!(matches!(
+            remote.build(),
+            Err(ConfigError::MissingSetting(AdapterKind::Remote, "url"))
+        ));
+        let tcp = ByteServiceConfig {
+            kind: AdapterKind::Tcp,
+            url: Some("http://localhost:8080".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            tcp.build(),
+            Err(ConfigError::MissingSetting(AdapterKind::Tcp, "address"))
+        ));
+        // Kinds are named as they are configured.
+        assert_eq!(
+            tcp.build().err().unwrap().to_string(),
+            "byte_service.address is required by the tcp adapter"
+        );
+        let uds = ByteServiceConfig {
+            kind: AdapterKind::Uds,
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        assert!(matches!(
+            uds.build(),
+            Err(ConfigError::MissingSetting(AdapterKind::Uds, "socket"))
+        ));
+        #[cfg(not(unix))]
+        assert!(matches!(
+            uds.build(),
+            Err(ConfigError::Unsupported(AdapterKind::Uds))
+        ));
+        let subprocess = ByteServiceConfig {
+            kind: AdapterKind::Subprocess,
+            command: Some(vec![]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            subprocess.build(),
+            Err(ConfigError::MissingSetting(AdapterKind::Subprocess, "command"))
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+//! Client side of the binary protocol of `provider::frame`, over any stream transport.
+//! One connection carries every call: requests are written as they come, and
+//! a reader thread hands each answer to the caller waiting for its ID, so
+//! calls from many threads are in flight at once. A connection that fails is
+//! dropped, and the next call opens a new one.
+//! The adapter of each transport is a `FramedClient` of it.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use provider::frame::{self, Request, Response};
+use provider::Payload;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, ProviderAdapter, Value};
+
+/// A way of reaching the provider.
+pub trait Transport: Send + Sync + 'static {
+    type Stream: Read + Write + Send + 'static;
+
+    /// Opens a connection, as a handle to read from and a handle to write to.
+    fn connect(&self) -> io::Result<(Self::Stream, Self::Stream)>;
+
+    /// Closes both directions of a connection, which unblocks its reader.
+    fn shutdown(stream: &Self::Stream);
+}
+
+type Answer = Result<Boolean, ByteServiceError>;
+
+/// Calls of a connection that are waiting for their answer,
+/// with their index in the caller's batch.
+#[derive(Default)]
+struct Waiting {
+    callers: HashMap<u64, (usize, mpsc::Sender<(usize, Answer)>)>,
+    closed: bool,
+}
+
+struct Connection<T: Transport> {
+    writer: Mutex<T::Stream>,
+    waiting: Arc<Mutex<Waiting>>,
+}
+
+impl<T: Transport> Drop for Connection<T> {
+    fn drop(&mut self) {
+        let writer = self
+            .writer
+            .get_mut()
+            .unwrap_or_else(|error| error.into_inner());
+        T::shutdown(writer);
+    }
+}
+
+/// Adapter calling the provider over a single connection,
+/// opened on the first call and shared by clones.
+pub struct FramedClient<T: Transport> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T: Transport> {
+    transport: T,
+    timeout: Duration,
+    connection: Mutex<Option<Arc<Connection<T>>>>,
+    next_id: AtomicU64,
+}
+
+impl<T: Transport> Clone for FramedClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: Transport> FramedClient<T> {
+    /// `timeout` bounds every call, from sending the request to reading the answer.
+    pub(crate) fn with_transport(transport: T, timeout: Duration) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                transport,
+                timeout,
+                connection: Mutex::new(None),
+                next_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Pipelines the requests, and returns their answers in order,
+    /// or the first error. Payloads too long for a frame are not sent.
+    fn call(&self, payloads: Vec<Payload>) -> Result<Vec<Boolean>, ByteServiceError> {
+        let ids: Vec<u64> = payloads
+            .iter()
+            .map(|_| self.shared.next_id.fetch_add(1, Ordering::Relaxed))
+            .collect();
+        let mut frames = Vec::new();
+        for (id, payload) in ids.iter().zip(payloads) {
+            let request = Request { id: *id, payload }
+                .encode()
+                .map_err(|error| ByteServiceError::InvalidInput(error.to_string()))?;
+            frames.extend(request);
+        }
+
+        let connection = self.connection()?;
+        let (sender, receiver) = mpsc::channel();
+        {
+            let mut waiting = connection
+                .waiting
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if waiting.closed {
+                return Err(ByteServiceError::Unavailable);
+            }
+            for (index, id) in ids.iter().enumerate() {
+                waiting.callers.insert(*id, (index, sender.clone()));
+            }
+        }
+        // Only the reader holds senders now, so a closed connection disconnects the receiver.
+        drop(sender);
+
+        {
+            let mut writer = connection
+                .writer
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+            if let Err(error) = writer.write_all(&frames).and_then(|()| writer.flush()) {
+                T::shutdown(&writer);
+                return Err(convert_error(error));
+            }
+        }
+
+        let deadline = Instant::now() + self.shared.timeout;
+        let mut answers: Vec<Option<Answer>> = ids.iter().map(|_| None).collect();
+        for _ in 0..ids.len() {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok((index, answer)) => answers[index] = Some(answer),
+                Err(RecvTimeoutError::Timeout) => {
+                    // Late answers are then dropped by the reader.
+                    let mut waiting = connection
+                        .waiting
+                        .lock()
+                        .unwrap_or_else(|error| error.into_inner());
+                    for id in &ids {
+                        waiting.callers.remove(id);
+                    }
+                    return Err(ByteServiceError::Timeout);
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(ByteServiceError::Unavailable),
+            }
+        }
+        answers.into_iter().flatten().collect()
+    }
+
+    /// The open connection, or a new one when there is none or it was closed.
+    fn connection(&self) -> Result<Arc<Connection<T>>, ByteServiceError> {
+        let mut connection = self
+            .shared
+            .connection
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        if let Some(current) = connection.as_ref() {
+            if !current
+                .waiting
+                .lock()
+                .unwrap_or_else(|error| error.into_inner())
+                .closed
+            {
+                return Ok(Arc::clone(current));
+            }
+        }
+        let (reader, writer) = self.shared.transport.connect().map_err(convert_error)?;
+        let waiting = Arc::new(Mutex::new(Waiting::default()));
+        let reading = Arc::clone(&waiting);
+        thread::Builder::new()
+            .name("provider-reader".into())
+            .spawn(move || read_answers(reader, reading))
+            .map_err(|error| ByteServiceError::Internal(error.to_string()))?;
+        let current = Arc::new(Connection {
+            writer: Mutex::new(writer),
+            waiting,
+        });
+        *connection = Some(Arc::clone(&current));
+        Ok(current)
+    }
+}
+
+impl<T: Transport> ByteService for FramedClient<T> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.is_zero_value(&Value::Byte(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        self.call(bytes.iter().map(|byte| Payload::U8(byte.0)).collect())
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let mut answers = self.call(vec![ProviderAdapter::convert_value(value)])?;
+        Ok(answers.remove(0))
+    }
+
+    /// The binary protocol cannot evaluate predicates.
+    fn evaluate(&self, _byte: Byte, _predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        Err(ByteServiceError::Internal("unsupported".into()))
+    }
+}
+
+/// Hands answers to their callers until the connection ends,
+/// then marks it closed, which fails the calls still waiting.
+fn read_answers(mut reader: impl Read, waiting: Arc<Mutex<Waiting>>) {
+    let result = loop {
+        let frame = match frame::read_frame(&mut reader) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break Ok(()),
+            Err(error) => break Err(error),
+        };
+        let response = match Response::decode(&frame) {
+            Ok(response) => response,
+            Err(error) => break Err(error),
+        };
+        let caller = waiting
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .callers
+            .remove(&response.id);
+        if let Some((index, sender)) = caller {
+            let answer = response
+                .result
+                .map(|outcome| Boolean(outcome.0))
+                .map_err(ProviderAdapter::convert_error);
+            let _ = sender.send((index, answer));
+        }
+    };
+    if let Err(error) = result {
+        tracing::warn!(error = %error, "provider connection failed");
+    }
+    let mut waiting = waiting.lock().unwrap_or_else(|error| error.into_inner());
+    waiting.closed = true;
+    waiting.callers.clear();
+}
+
+/// Timeouts when the provider was too slow, and unavailability otherwise,
+/// except for addresses that could never work.
+fn convert_error(error: io::Error) -> ByteServiceError {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ByteServiceError::Timeout,
+        io::ErrorKind::InvalidInput => ByteServiceError::Internal(error.to_string()),
+        _ => ByteServiceError::Unavailable,
+    }
+}
@@ -0,0 +1,269 @@
+//! ByteService backed by a provider running in another process,
+//! reached over HTTP with the bodies of `provider::wire`.
+
+use std::error::Error as _;
+use std::io;
+use std::time::Duration;
+
+use provider::wire::{
+    self, BatchRequest, BatchResponse, ErrorResponse, FunctionalityRequest, FunctionalityResponse,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, ProviderAdapter, Value};
+
+/// Adapter calling a remote provider, e.g. `provider-server`.
+/// Connections are pooled and reused across calls, and across clones.
+#[derive(Clone)]
+pub struct HttpProviderAdapter {
+    agent: ureq::Agent,
+    base_url: String,
+}
+
+impl HttpProviderAdapter {
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Idle connections kept open to the provider.
+    pub const POOL_SIZE: usize = 16;
+
+    /// Calls the provider at `base_url`, e.g. `http://localhost:8080`, with the default timeouts.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_timeouts(
+            base_url,
+            Self::DEFAULT_CONNECT_TIMEOUT,
+            Self::DEFAULT_TIMEOUT,
+        )
+    }
+
+    /// `connect_timeout` bounds opening a connection, `timeout` bounds a whole call.
+    pub fn with_timeouts(
+        base_url: impl Into<String>,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(connect_timeout)
+            .timeout(timeout)
+            .max_idle_connections_per_host(Self::POOL_SIZE)
+            .build();
+        Self {
+            agent,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn post<B: Serialize, R: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<R, ByteServiceError> {
+        let url = format!("{}{}", self.base_url, path);
+        let _entered = tracing::debug_span!("http.post", url = %url).entered();
+        let response = self
+            .agent
+            .post(&url)
+            .send_json(body)
+            .map_err(convert_error)?;
+        response
+            .into_json()
+            .map_err(|error| ByteServiceError::Internal(format!("invalid response: {}", error)))
+    }
+}
+
+impl ByteService for HttpProviderAdapter {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.is_zero_value(&Value::Byte(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let request = BatchRequest {
+            payloads: bytes
+                .iter()
+                .map(|byte| provider::Payload::U8(byte.0))
+                .collect(),
+        };
+        let response: BatchResponse = self.post(wire::FUNCTIONALITY_BATCH_PATH, &request)?;
+        if response.outcomes.len() != bytes.len() {
+            return Err(ByteServiceError::Internal(format!(
+                "invalid response: {} outcomes for {} payloads",
+                response.outcomes.len(),
+                bytes.len()
+            )));
+        }
+        Ok(response
+            .outcomes
+            .into_iter()
+            .map(|outcome| Boolean(outcome.0))
+            .collect())
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let request = FunctionalityRequest {
+            payload: ProviderAdapter::convert_value(value),
+        };
+        let response: FunctionalityResponse = self.post(wire::FUNCTIONALITY_PATH, &request)?;
+        Ok(Boolean(response.outcome.0))
+    }
+
+    /// The provider-server HTTP API cannot evaluate predicates.
+    fn evaluate(&self, _byte: Byte, _predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        Err(ByteServiceError::Internal("unsupported".into()))
+    }
+}
+
+/// Statuses are read as described in `provider::wire`; transport failures
+/// are timeouts when the provider was too slow, and unavailability otherwise.
+fn convert_error(error: ureq::Error) -> ByteServiceError {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let message = response
+                .into_json::<ErrorResponse>()
+                .map(|body| body.error)
+                .unwrap_or_else(|_| format!("status {}", status));
+            match status {
+                400 | 422 => ByteServiceError::InvalidInput(message),
+                429 | 502 | 503 => ByteServiceError::Unavailable,
+                504 => ByteServiceError::Timeout,
+                _ => ByteServiceError::Internal(message),
+            }
+        }
+        ureq::Error::Transport(transport) => {
+            let timed_out = transport
+                .source()
+                .and_then(|source| source.downcast_ref::<io::Error>())
+                .is_some_and(|error| {
+                    matches!(
+                        error.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    )
+                });
+            match transport.kind() {
+                _ if timed_out => ByteServiceError::Timeout,
+                ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => {
+                    ByteServiceError::Internal(transport.to_string())
+                }
+                _ => ByteServiceError::Unavailable,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider::{DefaultProvider, MockProvider, ProviderError};
+    use provider_server::SharedProvider;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+    use test_support::{BackgroundServer, Response, StubServer};
+
+    /// Runs `provider-server` in-process until the server is dropped.
+    fn start(provider: SharedProvider) -> (SocketAddr, BackgroundServer) {
+        BackgroundServer::tcp(move |listener, shutdown| {
+            provider_server::serve(listener, provider, shutdown, Duration::ZERO)
+        })
+    }
+
+    #[test]
+    fn calls_the_provider() {
+        let (address, _server) = start(Arc::new(DefaultProvider));
+        let adapter = HttpProviderAdapter::new(format!("http://{}/", address));
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(adapter.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(
+            adapter.is_zero_many(&[Byte(0), Byte(2)]),
+            Ok(vec![Boolean(true), Boolean(false)])
+        );
+        assert_eq!(
+            adapter.is_zero_value(&Value::U64(1 << 40)),
+            Ok(Boolean(false))
+        );
+        assert_eq!(
+            adapter.evaluate(Byte(0), &Predicate::zero()),
+            Err(ByteServiceError::Internal("unsupported".into()))
+        );
+    }
+
+    #[test]
+    fn converts_errors() {
+        let mut provider = MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .returning(|payload| match payload {
+                provider::Payload::U8(1) => Err(ProviderError::InvalidPayload("odd".into())),
+                provider::Payload::U8(2) => Err(ProviderError::Unavailable),
+                _ => {
+                    std::thread::sleep(Duration::from_millis(500));
+                    Ok(provider::Outcome(true))
+                }
+            });
+        let (address, _server) = start(Arc::new(provider));
+        let adapter = HttpProviderAdapter::with_timeouts(
+            format!("http://{}", address),
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+        );
+        assert_eq!(
+            adapter.is_zero(Byte(1)),
+            Err(ByteServiceError::InvalidInput("odd".into()))
+        );
+        assert_eq!(adapter.is_zero(Byte(2)), Err(ByteServiceError::Unavailable));
+        assert_eq!(adapter.is_zero(Byte(3)), Err(ByteServiceError::Timeout));
+
+        // Nothing listens on a port that was just released.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let adapter = HttpProviderAdapter::new(format!("http://{}", closed));
+        assert_eq!(adapter.is_zero(Byte(0)), Err(ByteServiceError::Unavailable));
+    }
+
+    /// The adapter sends the documented bodies, once per call, reads the canned
+    /// answers, and times out on answers slower than its timeout.
+    #[test]
+    fn sends_wire_requests() {
+        let server = StubServer::start();
+        server
+            .expect("POST", "/v1/functionality")
+            .json_body(r#"{"payload":{"type":"u8","value":0}}"#)
+            .times(2)
+            .respond(Response::json(200, r#"{"outcome":true}"#));
+        server
+            .expect("POST", "/v1/functionality/batch")
+            .json_body(r#"{"payloads":[{"type":"u8","value":1},{"type":"u8","value":0}]}"#)
+            .times(1)
+            .respond(Response::json(200, r#"{"outcomes":[false,true]}"#));
+        server
+            .expect("POST", "/v1/functionality")
+            .json_body(r#"{"payload":{"type":"i16","value":-2}}"#)
+            .times(1)
+            .respond(Response::json(200, r#"{"outcome":false}"#).delay(Duration::from_millis(300)));
+        server
+            .expect("POST", "/v1/functionality")
+            .json_body(r#"{"payload":{"type":"u8","value":8}}"#)
+            .times(1)
+            .respond(Response::status(500).body("boom"));
+        let adapter = HttpProviderAdapter::with_timeouts(
+            server.url(),
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+        );
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(
+            adapter.is_zero_many(&[Byte(1), Byte(0)]),
+            Ok(vec![Boolean(false), Boolean(true)])
+        );
+        assert_eq!(
+            adapter.is_zero_value(&Value::I16(-2)),
+            Err(ByteServiceError::Timeout)
+        );
+        assert_eq!(
+            adapter.is_zero(Byte(8)),
+            Err(ByteServiceError::Internal("status 500".into()))
+        );
+    }
+}
@@ -0,0 +1,747 @@
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::Instrument;
+
+use predicate::Predicate;
+
+pub mod builder;
+pub mod cache;
+pub mod cassette;
+pub mod circuit_breaker;
+pub mod cli;
+pub mod config;
+mod framed;
+pub mod http;
+pub mod metrics;
+pub mod predicate;
+pub mod registry;
+pub mod retry;
+pub mod subprocess;
+pub mod tcp;
+pub mod telemetry;
+#[cfg(unix)]
+pub mod uds;
+
+/// Custom type
+/// With the `serde` feature, it is written as a bare number, e.g. `7`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Byte(pub u8);
+
+/// A Byte is asked to the provider, or to a Predicate, as a u8 payload.
+impl From<Byte> for provider::Payload {
+    fn from(byte: Byte) -> Self {
+        provider::Payload::U8(byte.0)
+    }
+}
+
+/// Wider inputs than a Byte: integers of any width, or whole buffers.
+/// A value is zero when all of its bytes are.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Value {
+    Byte(Byte),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// The bytes of the value, in little-endian order for integers.
+    pub fn to_bytes(&self) -> Vec<Byte> {
+        let bytes = match self {
+            Value::Byte(byte) => return vec![*byte],
+            Value::U16(value) => value.to_le_bytes().to_vec(),
+            Value::U32(value) => value.to_le_bytes().to_vec(),
+            Value::U64(value) => value.to_le_bytes().to_vec(),
+            Value::I8(value) => value.to_le_bytes().to_vec(),
+            Value::I16(value) => value.to_le_bytes().to_vec(),
+            Value::I32(value) => value.to_le_bytes().to_vec(),
+            Value::I64(value) => value.to_le_bytes().to_vec(),
+            Value::Bytes(bytes) => bytes.clone(),
+        };
+        bytes.into_iter().map(Byte).collect()
+    }
+}
+
+macro_rules! value_from {
+    ($($variant:ident($type:ty)),*) => {
+        $(
+            impl From<$type> for Value {
+                fn from(value: $type) -> Self {
+                    Value::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+value_from!(
+    Byte(Byte),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Bytes(Vec<u8>)
+);
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Self {
+        Value::Byte(Byte(value))
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(bytes: &[u8]) -> Self {
+        Value::Bytes(bytes.to_vec())
+    }
+}
+
+/// Another custom type
+/// With the `serde` feature, it is written as a bare boolean, e.g. `true`.
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Boolean(pub bool);
+
+/// Errors surfaced by a ByteService.
+/// Like Byte and Boolean, this is our own type: adapters translate
+/// the external errors into it, so callers never see provider types.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ByteServiceError {
+    InvalidInput(String),
+    Unavailable,
+    Timeout,
+    Internal(String),
+}
+
+impl fmt::Display for ByteServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteServiceError::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            ByteServiceError::Unavailable => write!(f, "service unavailable"),
+            ByteServiceError::Timeout => write!(f, "service timed out"),
+            ByteServiceError::Internal(reason) => write!(f, "internal error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ByteServiceError {}
+
+impl ByteServiceError {
+    /// Whether the same call may succeed if attempted again.
+    /// Transient failures are, while rejected inputs and internal errors are not.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ByteServiceError::Unavailable | ByteServiceError::Timeout)
+    }
+}
+
+/// ByteService will use an external dependency (Provider).
+/// To mock the external calls, we declare the ByteService interface
+/// and specify separately the implementation.
+#[cfg_attr(test, mockall::automock)]
+pub trait ByteService {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError>;
+
+    /// Batch version of is_zero. By default it loops over is_zero,
+    /// implementations backed by a batch-capable dependency should override it.
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        bytes.iter().map(|byte| self.is_zero(*byte)).collect()
+    }
+
+    /// Whether a wider value is zero. By default it checks every byte of the value
+    /// with is_zero_many, implementations handling wider values natively should override it.
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let answers = self.is_zero_many(&value.to_bytes())?;
+        Ok(Boolean(answers.iter().all(|answer| answer.0)))
+    }
+
+    /// Whether the byte matches the predicate. Implementations whose dependency
+    /// cannot evaluate predicates fail with `ByteServiceError::Internal("unsupported")`.
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError>;
+}
+
+/// Boxed services are services too, so decorators can wrap
+/// an implementation chosen at runtime.
+impl<S: ByteService + ?Sized> ByteService for Box<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        (**self).is_zero(byte)
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        (**self).is_zero_many(bytes)
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        (**self).is_zero_value(value)
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        (**self).evaluate(byte, predicate)
+    }
+}
+
+impl<S: ByteService + ?Sized> ByteService for Arc<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        (**self).is_zero(byte)
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        (**self).is_zero_many(bytes)
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        (**self).is_zero_value(value)
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        (**self).evaluate(byte, predicate)
+    }
+}
+
+/// Concrete implementation of the ByteService, using the external dependency.
+/// It handles converting to and from the external types.
+/// Should the library change, only this Adapter will need updating.
+pub struct ProviderAdapter;
+
+/// The Adapter every ProviderAdapter call goes through.
+static DEFAULT_ADAPTER: GenericProviderAdapter<provider::DefaultProvider> =
+    GenericProviderAdapter { provider: provider::DefaultProvider };
+
+impl ByteService for ProviderAdapter {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        DEFAULT_ADAPTER.is_zero(byte)
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        DEFAULT_ADAPTER.is_zero_many(bytes)
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        DEFAULT_ADAPTER.is_zero_value(value)
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        DEFAULT_ADAPTER.evaluate(byte, predicate)
+    }
+}
+
+/// The same Adapter, over any implementation of the provider's own trait,
+/// e.g. `provider::MockProvider` to test the conversions.
+/// Every call is traced in a span with the translated `payload`,
+/// then the `outcome` or the provider `error`.
+pub struct GenericProviderAdapter<P> {
+    provider: P,
+}
+impl<P: provider::Provider> GenericProviderAdapter<P> {
+    pub fn new(provider: P) -> Self {
+        Self { provider }
+    }
+
+    fn call(
+        span: tracing::Span,
+        call: impl FnOnce() -> Result<provider::Outcome, provider::ProviderError>,
+    ) -> Result<Boolean, ByteServiceError> {
+        let result = span.in_scope(call);
+        ProviderAdapter::record(&span, &result);
+        let provider_outcome = result.map_err(ProviderAdapter::convert_error)?;
+        Ok(Boolean(provider_outcome.0))
+    }
+}
+impl<P: provider::Provider> ByteService for GenericProviderAdapter<P> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.is_zero_value(&Value::Byte(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let provider_payloads: Vec<_> =
+            bytes.iter().map(|byte| provider::Payload::from(byte.0)).collect();
+        let span = tracing::debug_span!(
+            "provider.functionality_batch",
+            payload = ?provider_payloads,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let provider_outcomes = span.in_scope(|| self.provider.functionality_batch(&provider_payloads));
+        let outcomes: Vec<bool> = provider_outcomes.iter().map(|outcome| outcome.0).collect();
+        span.record("outcome", tracing::field::debug(&outcomes));
+        Ok(outcomes.into_iter().map(Boolean).collect())
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let provider_payload = ProviderAdapter::convert_value(value);
+        let span = tracing::debug_span!(
+            "provider.try_functionality",
+            payload = ?provider_payload,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        Self::call(span, || self.provider.try_functionality(provider_payload))
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        let provider_payload = provider::Payload::from(byte);
+        let span = tracing::debug_span!(
+            "provider.try_evaluate",
+            payload = ?provider_payload,
+            predicate = ?predicate,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        Self::call(span, || self.provider.try_evaluate(provider_payload, predicate))
+    }
+}
+
+/// ByteService answering as if every byte were the same one.
+/// Useful to run the binary without any provider, e.g. in staging.
+pub struct StubByteService {
+    value: Byte,
+}
+impl StubByteService {
+    /// Stubs every byte as 0, or as 1 when not `is_zero`.
+    pub fn new(is_zero: bool) -> Self {
+        Self::with_value(Byte(u8::from(!is_zero)))
+    }
+
+    /// Stubs every byte as `value`.
+    pub fn with_value(value: Byte) -> Self {
+        Self { value }
+    }
+}
+impl ByteService for StubByteService {
+    fn is_zero(&self, _byte: Byte) -> Result<Boolean, ByteServiceError> {
+        Ok(Boolean(self.value.0 == 0))
+    }
+
+    /// Predicates are evaluated on the stubbed value.
+    fn evaluate(&self, _byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        Ok(Boolean(predicate.matches(&self.value.into())))
+    }
+}
+
+/// Asynchronous counterpart of ByteService, for callers running on a runtime.
+/// It is mocked the same way, so async code can be unit tested without the provider.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait AsyncByteService {
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError>;
+}
+
+#[async_trait]
+impl AsyncByteService for Box<dyn AsyncByteService + Send + Sync> {
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        (**self).is_zero(byte).await
+    }
+}
+
+/// The same Adapter serves the async interface, using the async provider entry point.
+#[async_trait]
+impl AsyncByteService for ProviderAdapter {
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let provider_payload = provider::Payload::from(byte.0);
+        let span = tracing::debug_span!(
+            "provider.functionality_async",
+            payload = ?provider_payload,
+            outcome = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let result = provider::functionality_async(provider_payload)
+            .instrument(span.clone())
+            .await;
+        ProviderAdapter::record(&span, &result);
+        let provider_outcome = result.map_err(ProviderAdapter::convert_error)?;
+        Ok(Boolean(provider_outcome.0))
+    }
+}
+
+/// Serves any ByteService through the async interface.
+/// Calls run on the runtime's blocking pool, so a slow synchronous
+/// implementation never stalls other tasks.
+pub struct BlockingAdapter<S: ?Sized> {
+    inner: Arc<S>,
+}
+impl<S: ?Sized> BlockingAdapter<S> {
+    pub fn new(inner: Arc<S>) -> Self {
+        Self { inner }
+    }
+}
+#[async_trait]
+impl<S> AsyncByteService for BlockingAdapter<S>
+where
+    S: ByteService + Send + Sync + ?Sized + 'static,
+{
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || inner.is_zero(byte))
+            .await
+            .map_err(|error| ByteServiceError::Internal(error.to_string()))?
+    }
+}
+
+impl ProviderAdapter {
+    /// Records the answer of the provider on the span of its call.
+    fn record(span: &tracing::Span, result: &Result<provider::Outcome, provider::ProviderError>) {
+        match result {
+            Ok(outcome) => span.record("outcome", outcome.0),
+            Err(error) => span.record("error", tracing::field::display(error)),
+        };
+    }
+
+    pub(crate) fn convert_value(value: &Value) -> provider::Payload {
+        match value {
+            Value::Byte(byte) => provider::Payload::U8(byte.0),
+            Value::U16(value) => provider::Payload::U16(*value),
+            Value::U32(value) => provider::Payload::U32(*value),
+            Value::U64(value) => provider::Payload::U64(*value),
+            Value::I8(value) => provider::Payload::I8(*value),
+            Value::I16(value) => provider::Payload::I16(*value),
+            Value::I32(value) => provider::Payload::I32(*value),
+            Value::I64(value) => provider::Payload::I64(*value),
+            Value::Bytes(bytes) => provider::Payload::Bytes(bytes.clone()),
+        }
+    }
+
+    pub(crate) fn convert_error(error: provider::ProviderError) -> ByteServiceError {
+        match error {
+            provider::ProviderError::InvalidPayload(reason) => {
+                ByteServiceError::InvalidInput(reason)
+            }
+            provider::ProviderError::Unavailable => ByteServiceError::Unavailable,
+            provider::ProviderError::Timeout => ByteServiceError::Timeout,
+            provider::ProviderError::Internal(reason) => ByteServiceError::Internal(reason),
+        }
+    }
+}
+
+/// Another service.
+/// This does not use external dependencies, but we still declare its interface:
+/// mocking it lets tests drive the application's failure branch.
+#[cfg_attr(test, mockall::automock)]
+pub trait BooleanService {
+    fn is_true(&self, boolean: Boolean) -> bool;
+}
+
+impl<O: BooleanService + ?Sized> BooleanService for Box<O> {
+    fn is_true(&self, boolean: Boolean) -> bool {
+        (**self).is_true(boolean)
+    }
+}
+
+impl<O: BooleanService + ?Sized> BooleanService for Arc<O> {
+    fn is_true(&self, boolean: Boolean) -> bool {
+        (**self).is_true(boolean)
+    }
+}
+
+/// Default implementation of the BooleanService.
+pub struct DefaultBooleanService;
+impl BooleanService for DefaultBooleanService {
+    #[tracing::instrument(name = "boolean_service.is_true", skip_all, fields(boolean = boolean.0))]
+    fn is_true(&self, boolean: Boolean) -> bool {
+        boolean.0
+    }
+}
+
+/// Main state holder. It holds all the necessary services.
+/// Services are type parameters, so the compiler can inline calls to concrete
+/// services such as ProviderAdapter; see BoxedApplication for dynamic dispatch.
+pub struct Application<B, O = DefaultBooleanService> {
+    pub byte_service: B,
+    pub boolean_service: O,
+}
+impl<B: ByteService> Application<B> {
+    pub fn new(byte_service: B) -> Self {
+        Self::with_services(byte_service, DefaultBooleanService)
+    }
+}
+impl<B: ByteService, O: BooleanService> Application<B, O> {
+    pub fn with_services(byte_service: B, boolean_service: O) -> Self {
+        Self {
+            byte_service,
+            boolean_service,
+        }
+    }
+}
+
+/// Application holding trait objects, for services chosen at runtime.
+pub type BoxedApplication = Application<Box<dyn ByteService>, Box<dyn BooleanService>>;
+
+/// Async state holder. Same services as Application, with the async ByteService.
+pub struct AsyncApplication<B, O = DefaultBooleanService> {
+    pub byte_service: B,
+    pub boolean_service: O,
+}
+impl<B: AsyncByteService> AsyncApplication<B> {
+    pub fn new(byte_service: B) -> Self {
+        Self::with_services(byte_service, DefaultBooleanService)
+    }
+}
+impl<B: AsyncByteService, O: BooleanService> AsyncApplication<B, O> {
+    pub fn with_services(byte_service: B, boolean_service: O) -> Self {
+        Self {
+            byte_service,
+            boolean_service,
+        }
+    }
+}
+
+/// AsyncApplication holding trait objects, for services chosen at runtime.
+pub type BoxedAsyncApplication = AsyncApplication<
+    Box<dyn AsyncByteService + Send + Sync>,
+    Box<dyn BooleanService + Send + Sync>,
+>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit tests for Application without mocking external dependencies.
+    /// Since we don't control how expensive the external calls are,
+    /// we rarely want to do this.
+    #[test]
+    fn without_mocks() {
+        let app = Application::new( Box::new(ProviderAdapter));
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(app.byte_service.is_zero(Byte(1)), Ok(Boolean(false)));
+    }
+
+    /// Unit tests for Application mocking external dependencies.
+    /// We are not really mocking the external calls themselves,
+    /// rather the crate's adapter - which returns ad hoc values directly.
+    #[test]
+    fn with_mocks() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(1)))
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        let app = Application::new( Box::new(mock));
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(false)));
+        assert_eq!(app.byte_service.is_zero(Byte(1)), Ok(Boolean(true)));
+    }
+
+    /// The batch call is mocked like any other method,
+    /// independently of the single-byte expectations.
+    #[test]
+    fn with_batch_mocks() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero().never();
+        mock.expect_is_zero_many()
+            .withf(|bytes| bytes == [Byte(0), Byte(1)])
+            .times(1)
+            .returning(|_| Ok(vec![Boolean(false), Boolean(true)]));
+        let app = Application::new(Box::new(mock));
+        assert_eq!(
+            app.byte_service.is_zero_many(&[Byte(0), Byte(1)]),
+            Ok(vec![Boolean(false), Boolean(true)])
+        );
+    }
+
+    /// The batch path of the adapter agrees with the single-byte path.
+    #[test]
+    fn batch_without_mocks() {
+        let app = Application::new(Box::new(ProviderAdapter));
+        let bytes: Vec<_> = (0..=u8::MAX).map(Byte).collect();
+        let expected: Result<Vec<_>, _> =
+            bytes.iter().map(|byte| app.byte_service.is_zero(*byte)).collect();
+        assert_eq!(app.byte_service.is_zero_many(&bytes), expected);
+    }
+
+    /// The same mocks plug into the boxed Application.
+    #[test]
+    fn boxed_with_mocks() {
+        let mut byte_service = MockByteService::new();
+        byte_service
+            .expect_is_zero()
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        let mut boolean_service = MockBooleanService::new();
+        boolean_service.expect_is_true().times(1).returning(|_| false);
+        let app: BoxedApplication =
+            Application::with_services(Box::new(byte_service), Box::new(boolean_service));
+        let is_zero = app.byte_service.is_zero(Byte(0)).unwrap();
+        assert!(!app.boolean_service.is_true(is_zero));
+    }
+
+    /// Failures are mocked the same way: the adapter's error type
+    /// is ours, so no provider error needs to be constructed.
+    #[test]
+    fn with_failing_mocks() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .times(1)
+            .returning(|_| Err(ByteServiceError::Timeout));
+        let app = Application::new( Box::new(mock));
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+    }
+
+    /// The async interface is mocked just like the sync one,
+    /// only the test itself needs a runtime.
+    #[tokio::test]
+    async fn with_async_mocks() {
+        let mut mock = MockAsyncByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        let app = AsyncApplication::new(mock);
+        assert_eq!(app.byte_service.is_zero(Byte(0)).await, Ok(Boolean(false)));
+    }
+
+    /// Any sync ByteService, mocks included, can back the async Application.
+    #[tokio::test]
+    async fn with_blocking_adapter() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        let app = AsyncApplication::new(BlockingAdapter::new(Arc::new(mock)));
+        assert_eq!(app.byte_service.is_zero(Byte(0)).await, Ok(Boolean(false)));
+    }
+
+    /// The async adapter agrees with the sync one.
+    #[tokio::test]
+    async fn async_without_mocks() {
+        let app = AsyncApplication::new(ProviderAdapter);
+        let is_zero = |byte| AsyncByteService::is_zero(&app.byte_service, byte);
+        assert_eq!(is_zero(Byte(0)).await, Ok(Boolean(true)));
+        assert_eq!(is_zero(Byte(1)).await, Ok(Boolean(false)));
+    }
+
+    /// The adapter translates every provider error at the boundary.
+    #[test]
+    fn provider_errors_are_converted() {
+        assert_eq!(
+            ProviderAdapter::convert_error(provider::ProviderError::Unavailable),
+            ByteServiceError::Unavailable
+        );
+        assert_eq!(
+            ProviderAdapter::convert_error(provider::ProviderError::Internal("boom".into())),
+            ByteServiceError::Internal("boom".into())
+        );
+    }
+
+    /// The JSON representations are part of the wire format, so they must not change.
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_representations() {
+        use serde_json::json;
+
+        assert_eq!(serde_json::to_value(Byte(7)).unwrap(), json!(7));
+        assert_eq!(serde_json::to_value(Boolean(true)).unwrap(), json!(true));
+        assert_eq!(
+            serde_json::to_value(provider::Outcome(false)).unwrap(),
+            json!(false)
+        );
+        assert_eq!(
+            serde_json::to_value(provider::Payload::U16(256)).unwrap(),
+            json!({"type": "u16", "value": 256})
+        );
+        assert_eq!(
+            serde_json::to_value(provider::Payload::Bytes(vec![0, 1])).unwrap(),
+            json!({"type": "bytes", "value": [0, 1]})
+        );
+        assert_eq!(
+            serde_json::from_value::<provider::Payload>(json!({"type": "i8", "value": -1}))
+                .unwrap(),
+            provider::Payload::I8(-1)
+        );
+        assert_eq!(serde_json::from_value::<Byte>(json!(255)).unwrap(), Byte(255));
+        assert!(serde_json::from_value::<Byte>(json!(256)).is_err());
+    }
+
+    /// The stub answers every question about its stubbed value.
+    #[test]
+    fn stub_evaluates_its_value() {
+        let stub = StubByteService::with_value(Byte(7));
+        assert_eq!(stub.is_zero(Byte(0)), Ok(Boolean(false)));
+        assert_eq!(stub.evaluate(Byte(0), &Predicate::Odd), Ok(Boolean(true)));
+        assert_eq!(stub.evaluate(Byte(0), &Predicate::Even), Ok(Boolean(false)));
+        let zero = StubByteService::new(true);
+        assert_eq!(zero.is_zero(Byte(1)), Ok(Boolean(true)));
+        assert_eq!(zero.evaluate(Byte(1), &Predicate::zero()), Ok(Boolean(true)));
+    }
+
+    /// The provider is mocked directly, with the mock shipped by the provider crate.
+    #[test]
+    fn with_provider_mock() {
+        let mut provider = provider::MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .with(mockall::predicate::eq(provider::Payload::U8(7)))
+            .times(1)
+            .returning(|_| Err(provider::ProviderError::Timeout));
+        provider
+            .expect_try_evaluate()
+            .withf(|payload, predicate| {
+                *payload == provider::Payload::U8(7) && *predicate == provider::Predicate::Odd
+            })
+            .times(1)
+            .returning(|_, _| Ok(provider::Outcome(true)));
+        let adapter = GenericProviderAdapter::new(provider);
+        assert_eq!(adapter.is_zero(Byte(7)), Err(ByteServiceError::Timeout));
+        assert_eq!(adapter.evaluate(Byte(7), &Predicate::Odd), Ok(Boolean(true)));
+    }
+
+    /// ProviderAdapter classifies wider values natively, and agrees with
+    /// the default implementation checking them byte by byte.
+    #[test]
+    fn wider_values() {
+        let values = [
+            Value::from(0u8),
+            Value::from(256u16),
+            Value::from(0u32),
+            Value::from(u64::MAX),
+            Value::from(-1i8),
+            Value::from(0i16),
+            Value::from(i32::MIN),
+            Value::from(0i64),
+            Value::from(vec![0, 0, 0]),
+            Value::from(&[0, 1][..]),
+            Value::from(Vec::new()),
+        ];
+        let expected = [
+            true, false, true, false, false, true, false, true, true, false, true,
+        ];
+        /// Relies on the default methods for batches and values.
+        struct ByteByByte;
+        impl ByteService for ByteByByte {
+            fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+                ByteService::is_zero(&ProviderAdapter, byte)
+            }
+
+            fn evaluate(
+                &self,
+                byte: Byte,
+                predicate: &Predicate,
+            ) -> Result<Boolean, ByteServiceError> {
+                ProviderAdapter.evaluate(byte, predicate)
+            }
+        }
+        for (value, expected) in values.iter().zip(expected) {
+            assert_eq!(ProviderAdapter.is_zero_value(value), Ok(Boolean(expected)));
+            assert_eq!(ByteByByte.is_zero_value(value), Ok(Boolean(expected)));
+        }
+    }
+}
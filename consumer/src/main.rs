@@ -1,96 +1,111 @@
-/// Custom type
-#[derive(PartialEq, Eq, Debug)]
-struct Byte(u8);
+use std::io::{IsTerminal, Read};
+use std::process::ExitCode;
 
-/// Another custom type
-#[derive(PartialEq, Eq, Debug)]
-struct Boolean(bool);
+use consumer::builder::{ApplicationBuilder, BuildError};
+use consumer::cli::{self, CliError, Command, Input, OutputFormat, EXIT_ERROR};
+use consumer::config::{AdapterKind, Config};
+use consumer::metrics::Metrics;
+use consumer::telemetry::{self, LogFormat};
+use consumer::{AsyncApplication, AsyncByteService, BooleanService, Byte};
 
-/// ByteService will use an external dependency (Provider).
-/// To mock the external calls, we declare the ByteService interface
-/// and specify separately the implementation.
-#[cfg_attr(test, mockall::automock)]
-trait ByteService {
-    fn is_zero(&self, byte: Byte) -> Boolean;
-}
-
-/// Concrete implementation of the ByteService, using the external dependency.
-/// It handles converting to and from the external types.
-/// Should the library change, only this Adapter will need updating.
-struct ProviderAdapter;
-impl ByteService for ProviderAdapter {
-    fn is_zero(&self, byte: Byte) -> Boolean {
-        let provider_payload = provider::Payload(byte.0);
-        let provider_outcome = provider::functionality(provider_payload);
-        Boolean(provider_outcome.0)
+/// Bin entrypoint.
+/// Exits with 1 when some byte is not zero, and with 2 on usage or service errors.
+#[tokio::main]
+async fn main() -> ExitCode {
+    telemetry::init(LogFormat::from_env());
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Check(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("Error: {}.\n\n{}", error, cli::USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let bytes = match options.input {
+        Input::Args(bytes) => bytes,
+        // A terminal would wait for bytes the user did not know to type.
+        Input::Stdin if std::io::stdin().is_terminal() => {
+            eprintln!("Error: {}.\n\n{}", CliError::NoBytes, cli::USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+        Input::Stdin => {
+            let mut text = String::new();
+            if let Err(error) = std::io::stdin().read_to_string(&mut text) {
+                eprintln!("Error: cannot read stdin: {}.", error);
+                return ExitCode::from(EXIT_ERROR);
+            }
+            match cli::parse_bytes(&text) {
+                Ok(bytes) => bytes,
+                Err(error) => {
+                    eprintln!("Error: {}.", error);
+                    return ExitCode::from(EXIT_ERROR);
+                }
+            }
+        }
+    };
+    if let Some(address) = options.metrics_listen {
+        if let Err(error) = Metrics::global().serve(address) {
+            eprintln!("Error: cannot serve metrics on {}: {}.", address, error);
+            return ExitCode::from(EXIT_ERROR);
+        }
     }
-}
 
-/// Another service.
-/// This does not use external dependencies, so we could use it statically
-/// or dynamically (depending on our unit testing needs).
-struct BooleanService;
-impl BooleanService {
-    fn is_true(&self, boolean: Boolean) -> bool {
-        boolean.0
+    let config = match Config::load(options.config.as_deref()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Error: {}.", error);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let kind = config.byte_service.kind;
+    let builder = ApplicationBuilder::new()
+        .byte_service_config(config.byte_service)
+        .metrics(Metrics::global())
+        .tracing();
+    // The in-process provider is cheap enough to call from the runtime itself.
+    let exit_code = match kind {
+        AdapterKind::Provider => run(builder.build_in_process(), bytes, options.format).await,
+        _ => run(builder.build_async(), bytes, options.format).await,
+    };
+    if options.metrics_dump {
+        eprint!("{}", Metrics::global().render());
     }
-}
-
-/// Main state holder. It holds all the necessary services.
-struct Application {
-    byte_service: Box<dyn ByteService>,
-    boolean_service: BooleanService,
-}
-impl Application {
-    fn new(byte_service: Box<dyn ByteService>) -> Self {
-        Self {
-            byte_service,
-            boolean_service: BooleanService,
+    // A single pass is over too soon to be scraped.
+    if let Some(address) = options.metrics_listen {
+        eprintln!("Serving metrics on http://{}/metrics, interrupt to exit.", address);
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            eprintln!("Error: cannot wait for an interrupt: {}.", error);
         }
     }
+    ExitCode::from(exit_code)
 }
 
-
-/// Bin entrypoint.
-fn main() {
-    let app = Application::new( Box::new(ProviderAdapter));
-    let is_zero = app.byte_service.is_zero(Byte(0));
-    match app.boolean_service.is_true(is_zero) {
-        true => println!("All good."),
-        false => panic!("Whoops."),
-    };
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    /// Unit tests for Application without mocking external dependencies.
-    /// Since we don't control how expensive the external calls are,
-    /// we rarely want to do this.
-    #[test]
-    fn without_mocks() {
-        let app = Application::new( Box::new(ProviderAdapter));
-        assert_eq!(app.byte_service.is_zero(Byte(0)), Boolean(true));
-        assert_eq!(app.byte_service.is_zero(Byte(1)), Boolean(false));
-    }
-
-    /// Unit tests for Application mocking external dependencies.
-    /// We are not really mocking the external calls themselves,
-    /// rather the crate's adapter - which returns ad hoc values directly.
-    #[test]
-    fn with_mocks() {
-        let mut mock = MockByteService::new();
-        mock.expect_is_zero()
-            .with(mockall::predicate::eq(Byte(0)))
-            .times(1)
-            .returning(|_| Boolean(false));
-        mock.expect_is_zero()
-            .with(mockall::predicate::eq(Byte(1)))
-            .times(1)
-            .returning(|_| Boolean(true));
-        let app = Application::new( Box::new(mock));
-        assert_eq!(app.byte_service.is_zero(Byte(0)), Boolean(false));
-        assert_eq!(app.byte_service.is_zero(Byte(1)), Boolean(true));
+async fn run<B, O>(
+    app: Result<AsyncApplication<B, O>, BuildError>,
+    bytes: Vec<Byte>,
+    format: OutputFormat,
+) -> u8
+where
+    B: AsyncByteService,
+    O: BooleanService,
+{
+    match app {
+        Ok(app) => {
+            cli::check(
+                &app,
+                bytes,
+                format,
+                &mut std::io::stdout(),
+                &mut std::io::stderr(),
+            )
+            .await
+        }
+        Err(error) => {
+            eprintln!("Error: {}.", error);
+            EXIT_ERROR
+        }
     }
 }
@@ -0,0 +1,411 @@
+//! Call counters and latency histograms, in the Prometheus text format.
+//! Series are labelled by service, method, adapter and outcome, so the
+//! provider traffic can be told apart from stubs, mocks or cache hits.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, BooleanService, Byte, ByteService, ByteServiceError, Value};
+
+/// How long a scraper may take to send its request or read the answer,
+/// as scrapes are answered one at a time.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const BUCKETS: [f64; 8] = [0.000_01, 0.000_1, 0.001, 0.01, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Labels {
+    service: &'static str,
+    method: &'static str,
+    adapter: String,
+    outcome: &'static str,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Registry of the metrics of every call.
+#[derive(Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<Labels, Histogram>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide registry, used when no other registry is given.
+    pub fn global() -> Arc<Metrics> {
+        static GLOBAL: OnceLock<Arc<Metrics>> = OnceLock::new();
+        Arc::clone(GLOBAL.get_or_init(Default::default))
+    }
+
+    /// Records a call that took `elapsed` and succeeded or not.
+    pub fn observe(
+        &self,
+        service: &'static str,
+        method: &'static str,
+        adapter: &str,
+        elapsed: Duration,
+        success: bool,
+    ) {
+        let labels = Labels {
+            service,
+            method,
+            adapter: adapter.to_string(),
+            outcome: if success { "ok" } else { "error" },
+        };
+        let seconds = elapsed.as_secs_f64();
+        let mut series = self
+            .series
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let histogram = series.entry(labels).or_default();
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Number of calls recorded with the given labels.
+    pub fn calls(&self, service: &str, method: &str, adapter: &str, success: bool) -> u64 {
+        let outcome = if success { "ok" } else { "error" };
+        let series = self
+            .series
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        series
+            .iter()
+            .filter(|(labels, _)| {
+                labels.service == service
+                    && labels.method == method
+                    && labels.adapter == adapter
+                    && labels.outcome == outcome
+            })
+            .map(|(_, histogram)| histogram.count)
+            .sum()
+    }
+
+    /// Renders every series in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let series = self
+            .series
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut text = String::new();
+        text.push_str("# HELP consumer_calls_total Service calls.\n");
+        text.push_str("# TYPE consumer_calls_total counter\n");
+        for (labels, histogram) in series.iter() {
+            let _ = writeln!(
+                text,
+                "consumer_calls_total{{{}}} {}",
+                labels.render(),
+                histogram.count
+            );
+        }
+        text.push_str("# HELP consumer_call_duration_seconds Service call latency.\n");
+        text.push_str("# TYPE consumer_call_duration_seconds histogram\n");
+        for (labels, histogram) in series.iter() {
+            let labels = labels.render();
+            for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    text,
+                    "consumer_call_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                text,
+                "consumer_call_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                text,
+                "consumer_call_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                text,
+                "consumer_call_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+        text
+    }
+
+    /// Serves `render` over HTTP on `address`, from a background thread,
+    /// for as long as the process runs. Returns the bound address,
+    /// which tells the actual port when binding port 0.
+    pub fn serve(self: Arc<Self>, address: SocketAddr) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A broken scrape only affects that scraper.
+                let _ = self.respond(stream);
+            }
+        });
+        Ok(address)
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+        stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+            Some("/metrics") => ("200 OK", "text/plain; version=0.0.4", self.render()),
+            _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+}
+
+impl Labels {
+    fn render(&self) -> String {
+        format!(
+            "service=\"{}\",method=\"{}\",adapter=\"{}\",outcome=\"{}\"",
+            self.service,
+            self.method,
+            escape(&self.adapter),
+            self.outcome
+        )
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Decorator recording the count and latency of every call of the inner ByteService.
+pub struct MeteredByteService<S> {
+    inner: S,
+    adapter: &'static str,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> MeteredByteService<S> {
+    /// Records to the global registry, labelled with the type name of the inner service.
+    pub fn new(inner: S) -> Self {
+        Self::named(inner, std::any::type_name::<S>())
+    }
+
+    /// Records to the global registry, labelled with `adapter`.
+    pub fn named(inner: S, adapter: &'static str) -> Self {
+        Self {
+            inner,
+            adapter,
+            metrics: Metrics::global(),
+        }
+    }
+
+    /// Records to `metrics` instead of the global registry.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    fn measure<T>(
+        &self,
+        method: &'static str,
+        call: impl FnOnce() -> Result<T, ByteServiceError>,
+    ) -> Result<T, ByteServiceError> {
+        let start = Instant::now();
+        let result = call();
+        self.observe(method, start, result.is_ok());
+        result
+    }
+
+    fn observe(&self, method: &'static str, start: Instant, success: bool) {
+        self.metrics.observe(
+            "byte_service",
+            method,
+            self.adapter,
+            start.elapsed(),
+            success,
+        );
+    }
+}
+
+impl<S: ByteService> ByteService for MeteredByteService<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.measure("is_zero", || self.inner.is_zero(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        self.measure("is_zero_many", || self.inner.is_zero_many(bytes))
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        self.measure("is_zero_value", || self.inner.is_zero_value(value))
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        self.measure("evaluate", || self.inner.evaluate(byte, predicate))
+    }
+}
+
+#[async_trait]
+impl<S: crate::AsyncByteService + Send + Sync> crate::AsyncByteService for MeteredByteService<S> {
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let start = Instant::now();
+        let result = self.inner.is_zero(byte).await;
+        self.observe("is_zero", start, result.is_ok());
+        result
+    }
+}
+
+/// Decorator recording the count and latency of every call of the inner BooleanService.
+pub struct MeteredBooleanService<O> {
+    inner: O,
+    service: &'static str,
+    metrics: Arc<Metrics>,
+}
+
+impl<O> MeteredBooleanService<O> {
+    /// Records to the global registry, labelled with the type name of the inner service.
+    pub fn new(inner: O) -> Self {
+        Self::named(inner, std::any::type_name::<O>())
+    }
+
+    /// Records to the global registry, labelled with `service`.
+    pub fn named(inner: O, service: &'static str) -> Self {
+        Self {
+            inner,
+            service,
+            metrics: Metrics::global(),
+        }
+    }
+
+    /// Records to `metrics` instead of the global registry.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+}
+
+impl<O: BooleanService> BooleanService for MeteredBooleanService<O> {
+    fn is_true(&self, boolean: Boolean) -> bool {
+        let start = Instant::now();
+        let is_true = self.inner.is_true(boolean);
+        self.metrics.observe(
+            "boolean_service",
+            "is_true",
+            self.service,
+            start.elapsed(),
+            true,
+        );
+        is_true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BooleanService, DefaultBooleanService, MockByteService, ProviderAdapter};
+    use std::io::Read;
+
+    #[test]
+    fn counts_calls_by_adapter_and_outcome() {
+        let metrics = Arc::new(Metrics::new());
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        let mock = MeteredByteService::new(mock).with_metrics(Arc::clone(&metrics));
+        let provider = MeteredByteService::named(ProviderAdapter, "provider")
+            .with_metrics(Arc::clone(&metrics));
+        assert!(mock.is_zero(Byte(0)).is_err());
+        provider.is_zero(Byte(0)).unwrap();
+        provider.is_zero(Byte(1)).unwrap();
+
+        let mock_name = std::any::type_name::<MockByteService>();
+        assert_eq!(
+            metrics.calls("byte_service", "is_zero", mock_name, false),
+            1
+        );
+        assert_eq!(
+            metrics.calls("byte_service", "is_zero", "provider", true),
+            2
+        );
+        let text = metrics.render();
+        assert!(text.contains(
+            "consumer_calls_total{service=\"byte_service\",method=\"is_zero\",\
+             adapter=\"provider\",outcome=\"ok\"} 2\n"
+        ));
+        assert!(text.contains(
+            "consumer_call_duration_seconds_count{service=\"byte_service\",method=\"is_zero\",\
+             adapter=\"provider\",outcome=\"ok\"} 2\n"
+        ));
+    }
+
+    #[test]
+    fn counts_value_calls() {
+        let metrics = Arc::new(Metrics::new());
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_value()
+            .times(1)
+            .returning(|_| Ok(Boolean(true)));
+        let service = MeteredByteService::named(mock, "mock").with_metrics(Arc::clone(&metrics));
+        assert_eq!(service.is_zero_value(&Value::U64(0)), Ok(Boolean(true)));
+        assert_eq!(
+            metrics.calls("byte_service", "is_zero_value", "mock", true),
+            1
+        );
+    }
+
+    #[test]
+    fn counts_boolean_service_calls() {
+        let metrics = Arc::new(Metrics::new());
+        let service = MeteredBooleanService::named(DefaultBooleanService, "default")
+            .with_metrics(Arc::clone(&metrics));
+        assert!(service.is_true(Boolean(true)));
+        assert!(!service.is_true(Boolean(false)));
+        assert_eq!(
+            metrics.calls("boolean_service", "is_true", "default", true),
+            2
+        );
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.observe("byte_service", "is_zero", "stub", Duration::ZERO, true);
+        let address = Arc::clone(&metrics)
+            .serve("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&metrics.render()));
+    }
+}
@@ -0,0 +1,55 @@
+//! Questions about a Byte, richer than "is zero".
+//! They are the provider's own `provider::Predicate`: a Byte is asked as
+//! the `Payload` it converts to, e.g. `predicate.matches(&byte.into())`
+//! to evaluate one locally.
+
+pub use provider::Predicate;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boolean, Byte, ByteService, MockByteService, ProviderAdapter};
+    use mockall::predicate::eq;
+
+    fn predicates() -> Vec<Predicate> {
+        vec![
+            Predicate::zero(),
+            Predicate::Range { min: 10, max: 20 },
+            Predicate::Bitmask {
+                mask: 0b1100,
+                value: 0b0100,
+            },
+            Predicate::Odd,
+            Predicate::In(vec![1, 2, 255]),
+            !Predicate::Even.and(Predicate::Range { min: 0, max: 127 }),
+            Predicate::Equals(7).or(Predicate::All(vec![])),
+            Predicate::Any(vec![]),
+        ]
+    }
+
+    /// The provider answers every predicate the way it is evaluated locally.
+    #[test]
+    fn provider_agrees_with_local_evaluation() {
+        for predicate in predicates() {
+            for byte in (0..=u8::MAX).map(Byte) {
+                assert_eq!(
+                    ProviderAdapter.evaluate(byte, &predicate),
+                    Ok(Boolean(predicate.matches(&byte.into()))),
+                    "{:?} on {:?}",
+                    predicate,
+                    byte
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_with_mocks() {
+        let mut mock = MockByteService::new();
+        mock.expect_evaluate()
+            .with(eq(Byte(3)), eq(Predicate::Odd))
+            .times(1)
+            .returning(|_, _| Ok(Boolean(false)));
+        assert_eq!(mock.evaluate(Byte(3), &Predicate::Odd), Ok(Boolean(false)));
+    }
+}
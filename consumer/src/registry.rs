@@ -0,0 +1,303 @@
+//! Type-keyed service registry.
+//! Binaries register how each service is built once; tests override
+//! single registrations, e.g. to swap ProviderAdapter for a mock,
+//! without touching the code that resolves them.
+
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::{Application, BooleanService, ByteService, DefaultBooleanService, ProviderAdapter};
+
+/// Shared ByteService, as resolved from a registry.
+pub type SharedByteService = Arc<dyn ByteService + Send + Sync>;
+/// Shared BooleanService, as resolved from a registry.
+pub type SharedBooleanService = Arc<dyn BooleanService + Send + Sync>;
+
+/// How often a factory is called.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Lifetime {
+    /// Once, on the first resolve; later resolves share the instance.
+    Singleton,
+    /// On every resolve.
+    Transient,
+}
+
+/// Errors while resolving a service.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum RegistryError {
+    /// Nothing is registered for this type.
+    NotRegistered(&'static str),
+    /// The factories depend on each other, in this order.
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::NotRegistered(name) => write!(f, "no service registered for {}", name),
+            RegistryError::Cycle(names) => write!(f, "dependency cycle: {}", names.join(" -> ")),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Builds an `Arc<T>`, boxed so that factories of any type share a map.
+type Factory =
+    Box<dyn Fn(&Resolver) -> Result<Box<dyn Any + Send + Sync>, RegistryError> + Send + Sync>;
+
+struct Registration {
+    name: &'static str,
+    lifetime: Lifetime,
+    factory: Factory,
+    instance: OnceLock<Box<dyn Any + Send + Sync>>,
+}
+
+/// Services registered by type, usually a trait object type such as
+/// `dyn ByteService + Send + Sync`, and resolved as `Arc`s of that type.
+#[derive(Default)]
+pub struct Registry {
+    registrations: HashMap<TypeId, Registration>,
+    overrides: HashMap<TypeId, Registration>,
+}
+
+/// Handle given to factories to resolve their own dependencies.
+pub struct Resolver<'a> {
+    registry: &'a Registry,
+    /// Types being built, to detect cycles.
+    stack: RefCell<Vec<(TypeId, &'static str)>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers how to build `T`, replacing any previous registration.
+    pub fn register<T, F>(&mut self, lifetime: Lifetime, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Resolver) -> Result<Arc<T>, RegistryError> + Send + Sync + 'static,
+    {
+        self.registrations
+            .insert(TypeId::of::<T>(), Registration::new(lifetime, factory));
+        self
+    }
+
+    /// Builds `T` with `factory` instead of its registration, until `clear_overrides`.
+    pub fn override_with<T, F>(&mut self, lifetime: Lifetime, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Resolver) -> Result<Arc<T>, RegistryError> + Send + Sync + 'static,
+    {
+        self.overrides
+            .insert(TypeId::of::<T>(), Registration::new(lifetime, factory));
+        self
+    }
+
+    /// Resolves `T` to the given instance instead of its registration.
+    pub fn override_instance<T>(&mut self, instance: Arc<T>) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        self.override_with::<T, _>(Lifetime::Singleton, move |_| Ok(Arc::clone(&instance)))
+    }
+
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+
+    pub fn resolve<T>(&self) -> Result<Arc<T>, RegistryError>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let resolver = Resolver {
+            registry: self,
+            stack: RefCell::new(Vec::new()),
+        };
+        resolver.resolve()
+    }
+
+    /// Resolves the services of an Application.
+    pub fn application(
+        &self,
+    ) -> Result<Application<SharedByteService, SharedBooleanService>, RegistryError> {
+        Ok(Application::with_services(
+            self.resolve::<dyn ByteService + Send + Sync>()?,
+            self.resolve::<dyn BooleanService + Send + Sync>()?,
+        ))
+    }
+
+    /// Registry with the default services as singletons:
+    /// ProviderAdapter and DefaultBooleanService.
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register::<dyn ByteService + Send + Sync, _>(Lifetime::Singleton, |_| {
+                Ok(Arc::new(ProviderAdapter))
+            })
+            .register::<dyn BooleanService + Send + Sync, _>(Lifetime::Singleton, |_| {
+                Ok(Arc::new(DefaultBooleanService))
+            });
+        registry
+    }
+}
+
+impl Registration {
+    fn new<T, F>(lifetime: Lifetime, factory: F) -> Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(&Resolver) -> Result<Arc<T>, RegistryError> + Send + Sync + 'static,
+    {
+        Self {
+            name: std::any::type_name::<T>(),
+            lifetime,
+            factory: Box::new(move |resolver| {
+                factory(resolver).map(|instance| Box::new(instance) as Box<dyn Any + Send + Sync>)
+            }),
+            instance: OnceLock::new(),
+        }
+    }
+}
+
+impl Resolver<'_> {
+    pub fn resolve<T>(&self) -> Result<Arc<T>, RegistryError>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let id = TypeId::of::<T>();
+        let name = std::any::type_name::<T>();
+        let registration = self
+            .registry
+            .overrides
+            .get(&id)
+            .or_else(|| self.registry.registrations.get(&id))
+            .ok_or(RegistryError::NotRegistered(name))?;
+
+        if let Some(position) = self.stack.borrow().iter().position(|(seen, _)| *seen == id) {
+            let mut cycle: Vec<_> = self.stack.borrow()[position..]
+                .iter()
+                .map(|(_, name)| *name)
+                .collect();
+            cycle.push(name);
+            return Err(RegistryError::Cycle(cycle));
+        }
+        self.stack.borrow_mut().push((id, registration.name));
+        let instance = self.instantiate(registration);
+        self.stack.borrow_mut().pop();
+
+        let instance = instance?;
+        let instance = instance
+            .downcast_ref::<Arc<T>>()
+            .expect("registrations are keyed by the type they build");
+        Ok(Arc::clone(instance))
+    }
+
+    fn instantiate<'r>(
+        &self,
+        registration: &'r Registration,
+    ) -> Result<InstanceRef<'r>, RegistryError> {
+        match registration.lifetime {
+            Lifetime::Transient => (registration.factory)(self).map(InstanceRef::Owned),
+            Lifetime::Singleton => {
+                if let Some(instance) = registration.instance.get() {
+                    return Ok(InstanceRef::Shared(instance.as_ref()));
+                }
+                let instance = (registration.factory)(self)?;
+                // If another thread won the race, its instance is kept and ours dropped.
+                let instance = registration.instance.get_or_init(|| instance);
+                Ok(InstanceRef::Shared(instance.as_ref()))
+            }
+        }
+    }
+}
+
+/// A freshly built instance, or one cached by a singleton registration.
+enum InstanceRef<'r> {
+    Owned(Box<dyn Any + Send + Sync>),
+    Shared(&'r (dyn Any + Send + Sync)),
+}
+
+impl std::ops::Deref for InstanceRef<'_> {
+    type Target = dyn Any + Send + Sync;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            InstanceRef::Owned(instance) => instance.as_ref(),
+            InstanceRef::Shared(instance) => *instance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CachedByteService;
+    use crate::{Boolean, Byte, MockByteService};
+
+    /// A mock replaces the registered ProviderAdapter until overrides are cleared.
+    #[test]
+    fn overrides_registered_services() {
+        let mut registry = Registry::with_defaults();
+        let app = registry.application().unwrap();
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(true)));
+
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        registry.override_instance::<dyn ByteService + Send + Sync>(Arc::new(mock));
+        let app = registry.application().unwrap();
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(false)));
+
+        registry.clear_overrides();
+        let app = registry.application().unwrap();
+        assert_eq!(app.byte_service.is_zero(Byte(0)), Ok(Boolean(true)));
+    }
+
+    #[test]
+    fn honours_lifetimes() {
+        let mut registry = Registry::with_defaults();
+        registry.register::<CachedByteService<SharedByteService>, _>(Lifetime::Singleton, |r| {
+            Ok(Arc::new(CachedByteService::new(r.resolve()?, 16)))
+        });
+        registry.register::<String, _>(Lifetime::Transient, |_| Ok(Arc::new(String::new())));
+        let first = registry
+            .resolve::<CachedByteService<SharedByteService>>()
+            .unwrap();
+        let second = registry
+            .resolve::<CachedByteService<SharedByteService>>()
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        let first = registry.resolve::<String>().unwrap();
+        let second = registry.resolve::<String>().unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn reports_missing_and_cyclic_services() {
+        let mut registry = Registry::new();
+        assert_eq!(
+            registry.resolve::<String>(),
+            Err(RegistryError::NotRegistered("alloc::string::String"))
+        );
+        registry.register::<String, _>(Lifetime::Singleton, |r| {
+            r.resolve::<u32>().map(|n| Arc::new(n.to_string()))
+        });
+        registry.register::<u32, _>(Lifetime::Transient, |r| {
+            r.resolve::<String>().map(|s| Arc::new(s.len() as u32))
+        });
+        assert_eq!(
+            registry.resolve::<String>(),
+            Err(RegistryError::Cycle(vec![
+                "alloc::string::String",
+                "u32",
+                "alloc::string::String"
+            ]))
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Retry decorator for any ByteService.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+
+/// How and when failed calls are attempted again.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    timeout: Option<Duration>,
+    attempt_threads: usize,
+    retryable: fn(&ByteServiceError) -> bool,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, backing off from 50ms, no timeout,
+    /// retrying the errors that `ByteServiceError::is_retryable` allows.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            jitter: 0.5,
+            timeout: None,
+            attempt_threads: 8,
+            retryable: ByteServiceError::is_retryable,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Total number of attempts, the first one included. At least one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The delay before the second attempt doubles for each further attempt,
+    /// up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Fraction (0 to 1) of each delay that is randomized,
+    /// so that many failing callers do not retry in lockstep.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Limit on the duration of each attempt. Late attempts fail with
+    /// `ByteServiceError::Timeout`, which may itself be retried.
+    ///
+    /// The inner call cannot be interrupted, so with a timeout attempts run on
+    /// `retry-attempt` threads, see `attempt_threads`. A timed out attempt keeps
+    /// its thread until the inner call returns, and attempts queued behind it
+    /// time out without running. Prefer the timeouts of the inner service
+    /// when it has some, e.g. `HttpProviderAdapter::with_timeouts`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Most threads running attempts at once when there is a timeout, 8 by default.
+    /// They are started when needed and reused, so an inner service that hangs
+    /// holds at most this many, and beyond it attempts wait for a free thread.
+    pub fn attempt_threads(mut self, attempt_threads: usize) -> Self {
+        self.attempt_threads = attempt_threads.max(1);
+        self
+    }
+
+    /// Which errors are worth another attempt.
+    pub fn retryable(mut self, retryable: fn(&ByteServiceError) -> bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Delay after the given failed attempt (starting from 1), before jitter.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Delay after the given failed attempt, with jitter.
+    fn delay(&self, attempt: u32) -> Duration {
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        self.base_delay(attempt).mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+/// Decorator retrying the failed calls of the inner ByteService.
+/// The inner service is shared with the threads that enforce timeouts:
+/// a timed out call keeps running in the background, but its answer is dropped
+/// (see `RetryPolicy::timeout`).
+///
+/// Backoff sleeps the calling thread, like the inner calls block it:
+/// from async code, call it through `BlockingAdapter`.
+pub struct RetryingByteService<S> {
+    inner: Arc<S>,
+    policy: RetryPolicy,
+    workers: Workers,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads running attempts, started on demand up to a limit and reused.
+/// They end once the decorator is dropped and their attempt returns.
+struct Workers {
+    jobs: mpsc::Sender<Job>,
+    queue: Arc<Mutex<mpsc::Receiver<Job>>>,
+    idle: Arc<AtomicUsize>,
+    started: AtomicUsize,
+    max: usize,
+}
+
+impl Workers {
+    fn new(max: usize) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        Self {
+            jobs,
+            queue: Arc::new(Mutex::new(queue)),
+            idle: Arc::new(AtomicUsize::new(0)),
+            started: AtomicUsize::new(0),
+            max,
+        }
+    }
+
+    fn run(&self, job: Job) -> Result<(), ByteServiceError> {
+        if self.idle.load(Ordering::SeqCst) == 0 && self.started.load(Ordering::SeqCst) < self.max {
+            self.start().map_err(|error| {
+                ByteServiceError::Internal(format!("cannot start an attempt: {}", error))
+            })?;
+        }
+        // The queue lives as long as `self`, so sending cannot fail.
+        let _ = self.jobs.send(job);
+        Ok(())
+    }
+
+    fn start(&self) -> std::io::Result<()> {
+        let queue = Arc::clone(&self.queue);
+        let idle = Arc::clone(&self.idle);
+        thread::Builder::new()
+            .name("retry-attempt".into())
+            .spawn(move || loop {
+                idle.fetch_add(1, Ordering::SeqCst);
+                let job = queue
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner())
+                    .recv();
+                idle.fetch_sub(1, Ordering::SeqCst);
+                match job {
+                    // A panicking call fails its attempt, not the thread.
+                    Ok(job) => {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    Err(_) => break,
+                }
+            })?;
+        self.started.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl<S> RetryingByteService<S>
+where
+    S: ByteService + Send + Sync + 'static,
+{
+    pub fn new(inner: S, policy: RetryPolicy) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            workers: Workers::new(policy.attempt_threads),
+            policy,
+        }
+    }
+
+    fn retry<T, F>(&self, call: F) -> Result<T, ByteServiceError>
+    where
+        T: Send + 'static,
+        F: Fn(&S) -> Result<T, ByteServiceError> + Clone + Send + 'static,
+    {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(call.clone()) {
+                Err(error)
+                    if attempt < self.policy.max_attempts && (self.policy.retryable)(&error) =>
+                {
+                    thread::sleep(self.policy.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn attempt<T, F>(&self, call: F) -> Result<T, ByteServiceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> Result<T, ByteServiceError> + Send + 'static,
+    {
+        let timeout = match self.policy.timeout {
+            Some(timeout) => timeout,
+            None => return call(&self.inner),
+        };
+        let inner = Arc::clone(&self.inner);
+        let (sender, receiver) = mpsc::channel();
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let skipped = Arc::clone(&abandoned);
+        self.workers.run(Box::new(move || {
+            // An attempt that timed out while queued is not worth running.
+            if !skipped.load(Ordering::SeqCst) {
+                let _ = sender.send(call(&inner));
+            }
+        }))?;
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                abandoned.store(true, Ordering::SeqCst);
+                Err(ByteServiceError::Timeout)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(ByteServiceError::Internal(
+                "inner service panicked".to_string(),
+            )),
+        }
+    }
+}
+
+impl<S> ByteService for RetryingByteService<S>
+where
+    S: ByteService + Send + Sync + 'static,
+{
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.retry(move |inner| inner.is_zero(byte))
+    }
+
+    /// The batch is retried as a whole, keeping the inner batch path.
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let bytes: Arc<[Byte]> = bytes.into();
+        self.retry(move |inner| inner.is_zero_many(&bytes))
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let value = Arc::new(value.clone());
+        self.retry(move |inner| inner.is_zero_value(&value))
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        let predicate = Arc::new(predicate.clone());
+        self.retry(move |inner| inner.evaluate(byte, &predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockByteService;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy::default().backoff(Duration::from_millis(1), Duration::from_millis(1))
+    }
+
+    /// Transient errors are retried until an attempt succeeds.
+    #[test]
+    fn retries_transient_errors() {
+        let calls = AtomicU32::new(0);
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(3)
+            .returning(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(ByteServiceError::Unavailable),
+                1 => Err(ByteServiceError::Timeout),
+                _ => Ok(Boolean(true)),
+            });
+        let service = RetryingByteService::new(mock, fast_policy());
+        assert_eq!(service.is_zero(Byte(0)), Ok(Boolean(true)));
+    }
+
+    /// Permanent errors are returned at once, transient ones after the last attempt.
+    #[test]
+    fn gives_up() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(0)))
+            .times(1)
+            .returning(|_| Err(ByteServiceError::InvalidInput("nope".into())));
+        mock.expect_is_zero()
+            .with(mockall::predicate::eq(Byte(1)))
+            .times(2)
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        let service = RetryingByteService::new(mock, fast_policy().max_attempts(2));
+        assert_eq!(
+            service.is_zero(Byte(0)),
+            Err(ByteServiceError::InvalidInput("nope".into()))
+        );
+        assert_eq!(service.is_zero(Byte(1)), Err(ByteServiceError::Unavailable));
+    }
+
+    /// Wider values reach the inner service as they are, retried the same way.
+    #[test]
+    fn retries_values() {
+        let calls = AtomicU32::new(0);
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero_value()
+            .with(mockall::predicate::eq(Value::U16(256)))
+            .times(2)
+            .returning(move |_| match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(ByteServiceError::Unavailable),
+                _ => Ok(Boolean(false)),
+            });
+        let service = RetryingByteService::new(mock, fast_policy());
+        assert_eq!(service.is_zero_value(&Value::U16(256)), Ok(Boolean(false)));
+    }
+
+    /// Slow attempts are cut short and count as timeouts.
+    #[test]
+    fn times_out_slow_calls() {
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero().returning(|_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(Boolean(true))
+        });
+        let policy = fast_policy()
+            .max_attempts(2)
+            .timeout(Duration::from_millis(10));
+        let service = RetryingByteService::new(mock, policy);
+        assert_eq!(service.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+    }
+
+    /// A hung inner service holds the attempt threads, not one thread per attempt:
+    /// attempts queued behind it time out without running.
+    #[test]
+    fn reuses_attempt_threads() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counted = Arc::clone(&calls);
+        let mut mock = MockByteService::new();
+        mock.expect_is_zero().returning(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(300));
+            Ok(Boolean(true))
+        });
+        let policy = fast_policy()
+            .max_attempts(4)
+            .timeout(Duration::from_millis(10))
+            .attempt_threads(1);
+        let service = RetryingByteService::new(mock, policy);
+        assert_eq!(service.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+        assert_eq!(service.workers.started.load(Ordering::SeqCst), 1);
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy::default()
+            .backoff(Duration::from_millis(10), Duration::from_millis(35))
+            .jitter(1.0);
+        assert_eq!(policy.base_delay(1), Duration::from_millis(10));
+        assert_eq!(policy.base_delay(2), Duration::from_millis(20));
+        assert_eq!(policy.base_delay(3), Duration::from_millis(35));
+        assert!(policy.delay(2) <= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,381 @@
+//! ByteService backed by a provider executable, for provider versions that
+//! only exist as CLIs.
+//!
+//! The child is spawned on the first call and kept running. Each request is a
+//! `provider::wire::FunctionalityRequest` written as one line of JSON to its
+//! stdin, and is answered by one line on its stdout: a `FunctionalityResponse`,
+//! or an `ErrorResponse` read by its kind, as a rejected payload when it has none.
+//! A child that exits, stops answering or answers garbage is killed, and spawned
+//! again on the next call. The last lines it wrote to stderr are part of the error
+//! of the call it failed.
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{self, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use provider::wire::{ErrorResponse, FunctionalityRequest, FunctionalityResponse};
+use serde::Deserialize;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, ProviderAdapter, Value};
+
+/// Lines of stderr kept for error messages.
+const STDERR_LINES: usize = 20;
+/// How long a child that closed its stdout gets to exit and finish writing to stderr.
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+/// Adapter running a provider executable, one call at a time.
+pub struct SubprocessByteService {
+    program: PathBuf,
+    args: Vec<OsString>,
+    timeout: Duration,
+    child: Mutex<Option<Child>>,
+}
+
+/// One line of the child's stdout.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Answer {
+    Outcome(FunctionalityResponse),
+    Error(ErrorResponse),
+}
+
+/// A running child, killed on drop.
+struct Child {
+    program: String,
+    process: process::Child,
+    stdin: ChildStdin,
+    stdout: mpsc::Receiver<io::Result<String>>,
+    stderr: Arc<Mutex<VecDeque<String>>>,
+    // Disconnected once stderr is closed.
+    stderr_closed: mpsc::Receiver<()>,
+}
+
+impl SubprocessByteService {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Runs `program`, found on the `PATH` unless it is a path, without arguments.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+            child: Mutex::new(None),
+        }
+    }
+
+    pub fn with_args<I, A>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<OsString>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Bounds every call. A child that takes longer is restarted,
+    /// as its late answer would otherwise be read as the next one.
+    /// The call fails like a crash, with an internal error carrying the end of the
+    /// child's stderr, since `Timeout` cannot carry it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn call(&self, payloads: Vec<provider::Payload>) -> Result<Vec<Boolean>, ByteServiceError> {
+        let mut slot = self.child.lock().unwrap();
+        let mut child = match slot.take() {
+            Some(mut child) => match child.process.try_wait() {
+                Ok(None) => child,
+                _ => {
+                    let error = child.failure("exited");
+                    tracing::warn!(error = %error, "restarting the provider");
+                    self.spawn()?
+                }
+            },
+            None => self.spawn()?,
+        };
+        // On failure, the child is dropped, which kills it.
+        let answers = child.exchange(payloads, self.timeout)?;
+        *slot = Some(child);
+        answers
+            .into_iter()
+            .map(|answer| match answer {
+                Answer::Outcome(response) => Ok(Boolean(response.outcome.0)),
+                Answer::Error(response) => Err(ProviderAdapter::convert_error(response.into())),
+            })
+            .collect()
+    }
+
+    fn spawn(&self) -> Result<Child, ByteServiceError> {
+        let program = self.program.display().to_string();
+        let mut process = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| {
+                ByteServiceError::Internal(format!("cannot run {}: {}", program, error))
+            })?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        let stderr = BufReader::new(process.stderr.take().unwrap());
+
+        let (lines, stdout_lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let tail = Arc::new(Mutex::new(VecDeque::new()));
+        let (closed, stderr_closed) = mpsc::channel();
+        let writing = Arc::clone(&tail);
+        thread::spawn(move || {
+            for line in stderr.lines().map_while(Result::ok) {
+                let mut tail = writing.lock().unwrap();
+                if tail.len() == STDERR_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            drop(closed);
+        });
+        tracing::debug!(program, pid = process.id(), "provider started");
+        Ok(Child {
+            program,
+            process,
+            stdin,
+            stdout: stdout_lines,
+            stderr: tail,
+            stderr_closed,
+        })
+    }
+}
+
+impl Child {
+    /// Writes every request, then reads as many answers.
+    /// Errors mean the child cannot be trusted with the next call.
+    fn exchange(
+        &mut self,
+        payloads: Vec<provider::Payload>,
+        timeout: Duration,
+    ) -> Result<Vec<Answer>, ByteServiceError> {
+        let count = payloads.len();
+        let mut requests = String::new();
+        for payload in payloads {
+            let request = serde_json::to_string(&FunctionalityRequest { payload })
+                .map_err(|error| ByteServiceError::Internal(error.to_string()))?;
+            requests.push_str(&request);
+            requests.push('\n');
+        }
+        if let Err(error) = self
+            .stdin
+            .write_all(requests.as_bytes())
+            .and_then(|()| self.stdin.flush())
+        {
+            return Err(self.failure(&format!("cannot be written to ({})", error)));
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut answers = Vec::with_capacity(count);
+        while answers.len() < count {
+            match self
+                .stdout
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(Ok(line)) => {
+                    let answer = serde_json::from_str(&line).map_err(|error| {
+                        ByteServiceError::Internal(format!(
+                            "invalid response from {}: `{}`: {}",
+                            self.program, line, error
+                        ))
+                    })?;
+                    answers.push(answer);
+                }
+                Ok(Err(error)) => {
+                    return Err(self.failure(&format!("cannot be read from ({})", error)))
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = self.process.kill();
+                    return Err(self.failure(&format!("timed out after {:?}", timeout)));
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(self.failure("exited")),
+            }
+        }
+        Ok(answers)
+    }
+
+    /// An error naming the child, with its exit status and the end of its stderr.
+    fn failure(&mut self, what: &str) -> ByteServiceError {
+        let mut message = format!("{} {}", self.program, what);
+        if let Some(status) = self.exit_status() {
+            message.push_str(&format!(" with {}", status));
+        }
+        let _ = self.stderr_closed.recv_timeout(EXIT_GRACE);
+        let tail = self.stderr_tail();
+        if !tail.is_empty() {
+            message.push_str(&format!(", stderr:\n{}", tail));
+        }
+        ByteServiceError::Internal(message)
+    }
+
+    /// The last lines written to stderr so far.
+    fn stderr_tail(&self) -> String {
+        let tail = self.stderr.lock().unwrap();
+        let lines: Vec<&str> = tail.iter().map(String::as_str).collect();
+        lines.join("\n")
+    }
+
+    fn exit_status(&mut self) -> Option<ExitStatus> {
+        let deadline = Instant::now() + EXIT_GRACE;
+        loop {
+            match self.process.try_wait() {
+                Ok(Some(status)) => return Some(status),
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                _ => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+impl ByteService for SubprocessByteService {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        self.is_zero_value(&Value::Byte(byte))
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        self.call(
+            bytes
+                .iter()
+                .map(|byte| provider::Payload::U8(byte.0))
+                .collect(),
+        )
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let mut answers = self.call(vec![ProviderAdapter::convert_value(value)])?;
+        Ok(answers.remove(0))
+    }
+
+    /// The line protocol of provider executables cannot evaluate predicates.
+    fn evaluate(&self, _byte: Byte, _predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        Err(ByteServiceError::Internal("unsupported".into()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// A provider CLI written in shell: zero is zero, seven is rejected,
+    /// eight finds it unavailable, and nine is rejected by an older version without kinds.
+    const ECHO_PROVIDER: &str = r#"
+        while read -r line; do
+            case "$line" in
+                *'"value":0}'*) echo '{"outcome":true}' ;;
+                *'"value":7}'*) echo '{"error":"seven","kind":"invalid_payload"}' ;;
+                *'"value":8}'*) echo '{"error":"provider unavailable","kind":"unavailable"}' ;;
+                *'"value":9}'*) echo '{"error":"nine"}' ;;
+                *) echo '{"outcome":false}' ;;
+            esac
+        done"#;
+
+    fn shell(script: &str) -> SubprocessByteService {
+        SubprocessByteService::new("sh").with_args(["-c", script])
+    }
+
+    #[test]
+    fn calls_the_child() {
+        let service = shell(ECHO_PROVIDER);
+        assert_eq!(service.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(service.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(
+            service.is_zero_many(&[Byte(0), Byte(2), Byte(0)]),
+            Ok(vec![Boolean(true), Boolean(false), Boolean(true)])
+        );
+        assert_eq!(service.is_zero_value(&Value::I16(-2)), Ok(Boolean(false)));
+        assert_eq!(
+            service.is_zero(Byte(7)),
+            Err(ByteServiceError::InvalidInput("seven".into()))
+        );
+        assert_eq!(service.is_zero(Byte(8)), Err(ByteServiceError::Unavailable));
+        assert_eq!(
+            service.is_zero(Byte(9)),
+            Err(ByteServiceError::InvalidInput("nine".into()))
+        );
+        // Failures the child answers do not restart it.
+        let pid = service.child.lock().unwrap().as_ref().unwrap().process.id();
+        assert_eq!(service.is_zero(Byte(0)), Ok(Boolean(true)));
+        let same_pid = service.child.lock().unwrap().as_ref().unwrap().process.id();
+        assert_eq!(pid, same_pid);
+    }
+
+    /// A crash fails the call with the child's stderr, and the next call restarts it.
+    #[test]
+    fn restarts_after_crashes() {
+        let service = shell(
+            r#"read -r line; echo '{"outcome":true}'
+               read -r line; echo 'out of memory' >&2; exit 3"#,
+        );
+        assert_eq!(service.is_zero(Byte(0)), Ok(Boolean(true)));
+        match service.is_zero(Byte(0)) {
+            Err(ByteServiceError::Internal(message)) => {
+                assert!(
+                    message.starts_with("sh exited with exit status: 3"),
+                    "{}",
+                    message
+                );
+                assert!(message.ends_with("stderr:\nout of memory"), "{}", message);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(service.is_zero(Byte(0)), Ok(Boolean(true)));
+    }
+
+    #[test]
+    fn restarts_slow_and_invalid_children() {
+        let service = shell(r#"read -r line; echo 'stuck' >&2; exec sleep 10"#)
+            .with_timeout(Duration::from_millis(100));
+        match service.is_zero(Byte(0)) {
+            Err(ByteServiceError::Internal(message)) => {
+                assert!(
+                    message.starts_with("sh timed out after 100ms"),
+                    "{}",
+                    message
+                );
+                assert!(message.ends_with("stderr:\nstuck"), "{}", message);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(service.child.lock().unwrap().is_none());
+
+        let service = shell(r#"while read -r line; do echo 'ok'; done"#);
+        assert!(matches!(
+            service.is_zero(Byte(0)),
+            Err(ByteServiceError::Internal(message)) if message.starts_with("invalid response from sh")
+        ));
+        assert!(service.child.lock().unwrap().is_none());
+
+        let service = SubprocessByteService::new("does/not/exist");
+        assert!(matches!(
+            service.is_zero(Byte(0)),
+            Err(ByteServiceError::Internal(message)) if message.starts_with("cannot run does/not/exist")
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+//! ByteService backed by a provider running in another process,
+//! reached over TCP with the binary protocol of `provider::frame`,
+//! e.g. `provider-server --tcp ADDRESS`.
+
+use std::io;
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::framed::{FramedClient, Transport};
+
+/// Adapter calling a remote provider over a single connection,
+/// opened on the first call and shared by clones.
+/// Calls from concurrent threads are multiplexed over it rather than queued.
+pub type TcpProviderAdapter = FramedClient<TcpTransport>;
+
+/// Connections of a `TcpProviderAdapter`.
+pub struct TcpTransport {
+    address: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl TcpProviderAdapter {
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Calls the provider at `address`, e.g. `localhost:9090`, with the default timeouts.
+    pub fn new(address: impl Into<String>) -> Self {
+        Self::with_timeouts(
+            address,
+            Self::DEFAULT_CONNECT_TIMEOUT,
+            Self::DEFAULT_TIMEOUT,
+        )
+    }
+
+    /// `connect_timeout` bounds opening a connection, `timeout` bounds a whole call.
+    pub fn with_timeouts(
+        address: impl Into<String>,
+        connect_timeout: Duration,
+        timeout: Duration,
+    ) -> Self {
+        let transport = TcpTransport {
+            address: address.into(),
+            connect_timeout,
+            timeout,
+        };
+        Self::with_transport(transport, timeout)
+    }
+}
+
+impl Transport for TcpTransport {
+    type Stream = TcpStream;
+
+    fn connect(&self) -> io::Result<(TcpStream, TcpStream)> {
+        let mut last_error = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no address for {}", self.address),
+        );
+        for address in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok((stream.try_clone()?, stream));
+                }
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn shutdown(stream: &TcpStream) {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::predicate::Predicate;
+    use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+    use provider::frame::{self, Request, Response};
+    use provider::{DefaultProvider, MockProvider, Outcome, Payload, ProviderError};
+    use provider_server::SharedProvider;
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+    use std::time::Instant;
+    use test_support::{BackgroundServer, SlowProvider};
+
+    /// Runs the binary front of `provider-server` in-process until the server is dropped.
+    fn start(provider: SharedProvider) -> (SocketAddr, BackgroundServer) {
+        BackgroundServer::tcp(move |listener, shutdown| {
+            provider_server::framed::serve_tcp(listener, provider, shutdown)
+        })
+    }
+
+    #[test]
+    fn calls_the_provider() {
+        let (address, _server) = start(Arc::new(DefaultProvider));
+        let adapter = TcpProviderAdapter::new(address.to_string());
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(adapter.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(
+            adapter.is_zero_many(&[Byte(0), Byte(2), Byte(0)]),
+            Ok(vec![Boolean(true), Boolean(false), Boolean(true)])
+        );
+        assert_eq!(adapter.is_zero_many(&[]), Ok(vec![]));
+        assert_eq!(
+            adapter.is_zero_value(&Value::I64(-1 << 40)),
+            Ok(Boolean(false))
+        );
+        assert_eq!(
+            adapter.evaluate(Byte(0), &Predicate::zero()),
+            Err(ByteServiceError::Internal("unsupported".into()))
+        );
+        assert_eq!(
+            adapter.is_zero_value(&Value::Bytes(vec![0, 0])),
+            Ok(Boolean(true))
+        );
+    }
+
+    /// Slow calls from many threads overlap on the one connection,
+    /// and each gets its own answer.
+    #[test]
+    fn multiplexes_concurrent_calls() {
+        let (address, _server) = start(Arc::new(SlowProvider::new(Duration::from_millis(200))));
+        let adapter = TcpProviderAdapter::new(address.to_string());
+        let start = Instant::now();
+        let calls: Vec<_> = (0..8u16)
+            .map(|value| {
+                let adapter = adapter.clone();
+                std::thread::spawn(move || adapter.is_zero_value(&Value::U16(value % 2)))
+            })
+            .collect();
+        for (value, call) in calls.into_iter().enumerate() {
+            assert_eq!(call.join().unwrap(), Ok(Boolean(value % 2 == 0)));
+        }
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn converts_errors() {
+        let mut provider = MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .returning(|payload| match payload {
+                Payload::U8(1) => Err(ProviderError::InvalidPayload("odd".into())),
+                Payload::U8(2) => Err(ProviderError::Unavailable),
+                Payload::U8(3) => {
+                    std::thread::sleep(Duration::from_millis(300));
+                    Ok(Outcome(true))
+                }
+                _ => Ok(Outcome(true)),
+            });
+        let (address, _server) = start(Arc::new(provider));
+        let adapter = TcpProviderAdapter::with_timeouts(
+            address.to_string(),
+            Duration::from_secs(1),
+            Duration::from_millis(100),
+        );
+        assert_eq!(
+            adapter.is_zero(Byte(1)),
+            Err(ByteServiceError::InvalidInput("odd".into()))
+        );
+        assert_eq!(adapter.is_zero(Byte(2)), Err(ByteServiceError::Unavailable));
+        assert_eq!(adapter.is_zero(Byte(3)), Err(ByteServiceError::Timeout));
+        // The late answer is dropped, and does not answer the next call.
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+
+        // Nothing listens on a port that was just released.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let adapter = TcpProviderAdapter::new(closed.to_string());
+        assert_eq!(adapter.is_zero(Byte(0)), Err(ByteServiceError::Unavailable));
+        // Payloads too long for a frame are rejected before connecting.
+        let too_long = Value::Bytes(vec![0; frame::MAX_FRAME_LEN as usize]);
+        assert!(matches!(
+            adapter.is_zero_value(&too_long),
+            Err(ByteServiceError::InvalidInput(message)) if message.ends_with("is too long")
+        ));
+        let adapter = TcpProviderAdapter::new("no port");
+        assert!(matches!(
+            adapter.is_zero(Byte(0)),
+            Err(ByteServiceError::Internal(_))
+        ));
+    }
+
+    /// A connection closed by the provider fails the calls in flight,
+    /// and the next call opens another.
+    #[test]
+    fn reconnects_after_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let adapter = TcpProviderAdapter::new(listener.local_addr().unwrap().to_string());
+        let server = std::thread::spawn(move || {
+            let (mut first, _) = listener.accept().unwrap();
+            frame::read_frame(&mut first).unwrap().unwrap();
+            drop(first);
+            let (mut second, _) = listener.accept().unwrap();
+            let request = Request::decode(&frame::read_frame(&mut second).unwrap().unwrap());
+            let response = Response {
+                id: request.unwrap().id,
+                result: Ok(Outcome(false)),
+            };
+            second.write_all(&response.encode().unwrap()).unwrap();
+        });
+        assert_eq!(adapter.is_zero(Byte(0)), Err(ByteServiceError::Unavailable));
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(false)));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Tracing instrumentation.
+//! Services emit spans through the `tracing` facade; the binary decides
+//! where they go by installing a subscriber with `init`.
+
+use std::io::IsTerminal;
+
+use async_trait::async_trait;
+use tracing::{field, Instrument, Span};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+use crate::predicate::Predicate;
+use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+
+/// Environment variable choosing the log format: `pretty` or `json`.
+pub const LOG_FORMAT_ENV: &str = "CONSUMER_LOG_FORMAT";
+
+/// Format of the emitted logs.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum LogFormat {
+    /// Human readable, for terminals.
+    Pretty,
+    /// One JSON object per line, for log shipping.
+    Json,
+}
+
+impl LogFormat {
+    /// `CONSUMER_LOG_FORMAT` if set and valid, else pretty on a terminal
+    /// and JSON when stderr is redirected.
+    pub fn from_env() -> Self {
+        match std::env::var(LOG_FORMAT_ENV).as_deref() {
+            Ok("pretty") => LogFormat::Pretty,
+            Ok("json") => LogFormat::Json,
+            _ if std::io::stderr().is_terminal() => LogFormat::Pretty,
+            _ => LogFormat::Json,
+        }
+    }
+}
+
+/// Installs the global subscriber, writing to stderr so that stdout only
+/// carries results. Spans are logged when they close, with their duration.
+/// Verbosity is read from `RUST_LOG` and defaults to warnings.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Pretty => builder.pretty().init(),
+        LogFormat::Json => builder.json().with_current_span(true).init(),
+    }
+}
+
+/// Decorator wrapping every call of the inner ByteService in a span,
+/// recording the input, the answer or the error.
+pub struct TracingByteService<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S> TracingByteService<S> {
+    /// Spans are labelled with the type name of the inner service.
+    pub fn new(inner: S) -> Self {
+        Self::named(inner, std::any::type_name::<S>())
+    }
+
+    /// Spans are labelled with `name`, e.g. when the inner type is a trait object.
+    pub fn named(inner: S, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+impl<S> TracingByteService<S> {
+    fn is_zero_span(&self, byte: Byte) -> Span {
+        tracing::info_span!(
+            "byte_service.is_zero",
+            service = self.name,
+            byte = byte.0,
+            is_zero = field::Empty,
+            error = field::Empty,
+        )
+    }
+}
+
+/// Records the answer in the `answer` field of `span`, or the error.
+fn record(span: &Span, answer: &str, result: &Result<Boolean, ByteServiceError>) {
+    match result {
+        Ok(boolean) => span.record(answer, boolean.0),
+        Err(error) => span.record("error", field::display(error)),
+    };
+}
+
+impl<S: ByteService> ByteService for TracingByteService<S> {
+    fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let span = self.is_zero_span(byte);
+        let result = span.in_scope(|| self.inner.is_zero(byte));
+        record(&span, "is_zero", &result);
+        result
+    }
+
+    fn is_zero_many(&self, bytes: &[Byte]) -> Result<Vec<Boolean>, ByteServiceError> {
+        let span = tracing::info_span!(
+            "byte_service.is_zero_many",
+            service = self.name,
+            count = bytes.len(),
+            error = field::Empty,
+        );
+        let _entered = span.enter();
+        let result = self.inner.is_zero_many(bytes);
+        if let Err(error) = &result {
+            span.record("error", field::display(error));
+        }
+        result
+    }
+
+    fn is_zero_value(&self, value: &Value) -> Result<Boolean, ByteServiceError> {
+        let span = tracing::info_span!(
+            "byte_service.is_zero_value",
+            service = self.name,
+            value = ?value,
+            is_zero = field::Empty,
+            error = field::Empty,
+        );
+        let result = span.in_scope(|| self.inner.is_zero_value(value));
+        record(&span, "is_zero", &result);
+        result
+    }
+
+    fn evaluate(&self, byte: Byte, predicate: &Predicate) -> Result<Boolean, ByteServiceError> {
+        let span = tracing::info_span!(
+            "byte_service.evaluate",
+            service = self.name,
+            byte = byte.0,
+            predicate = ?predicate,
+            matches = field::Empty,
+            error = field::Empty,
+        );
+        let result = span.in_scope(|| self.inner.evaluate(byte, predicate));
+        record(&span, "matches", &result);
+        result
+    }
+}
+
+/// The span covers the whole call, including the time the inner service is awaited.
+#[async_trait]
+impl<S: crate::AsyncByteService + Send + Sync> crate::AsyncByteService for TracingByteService<S> {
+    async fn is_zero(&self, byte: Byte) -> Result<Boolean, ByteServiceError> {
+        let span = self.is_zero_span(byte);
+        let result = self.inner.is_zero(byte).instrument(span.clone()).await;
+        record(&span, "is_zero", &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ApplicationBuilder;
+    use crate::{BooleanService, DefaultBooleanService, ProviderAdapter};
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// A closed span: its name and its recorded fields, formatted.
+    struct RecordedSpan {
+        name: &'static str,
+        fields: Vec<(&'static str, String)>,
+    }
+
+    struct Fields(Vec<(&'static str, String)>);
+
+    impl field::Visit for Fields {
+        fn record_debug(&mut self, field: &field::Field, value: &dyn fmt::Debug) {
+            self.0.push((field.name(), format!("{:?}", value)));
+        }
+
+        fn record_str(&mut self, field: &field::Field, value: &str) {
+            self.0.push((field.name(), value.to_string()));
+        }
+    }
+
+    /// Layer collecting spans as they close, for assertions.
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<Mutex<Vec<RecordedSpan>>>);
+
+    impl SpanRecorder {
+        /// Runs `f` with a subscriber recording its spans, and returns them.
+        fn record(f: impl FnOnce()) -> Vec<RecordedSpan> {
+            let recorder = SpanRecorder::default();
+            let subscriber = tracing_subscriber::registry().with(recorder.clone());
+            tracing::subscriber::with_default(subscriber, f);
+            let spans = std::mem::take(&mut *recorder.0.lock().unwrap());
+            spans
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+        fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+            let mut fields = Fields(Vec::new());
+            attributes.record(&mut fields);
+            let span = context.span(id).unwrap();
+            span.extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, context: Context<'_, S>) {
+            let span = context.span(id).unwrap();
+            let mut extensions = span.extensions_mut();
+            values.record(extensions.get_mut::<Fields>().unwrap());
+        }
+
+        fn on_close(&self, id: Id, context: Context<'_, S>) {
+            let span = context.span(&id).unwrap();
+            let fields = span.extensions_mut().remove::<Fields>().unwrap();
+            self.0.lock().unwrap().push(RecordedSpan {
+                name: span.name(),
+                fields: fields.0,
+            });
+        }
+    }
+
+    fn field<'a>(span: &'a RecordedSpan, name: &str) -> Option<&'a str> {
+        span.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// A call through the decorated adapter emits the provider span
+    /// nested in the service span, then the boolean service span.
+    #[test]
+    fn emits_spans() {
+        let spans = SpanRecorder::record(|| {
+            let service = TracingByteService::named(ProviderAdapter, "provider");
+            let is_zero = service.is_zero(Byte(0)).unwrap();
+            DefaultBooleanService.is_true(is_zero);
+        });
+        let names: Vec<_> = spans.iter().map(|span| span.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "provider.try_functionality",
+                "byte_service.is_zero",
+                "boolean_service.is_true"
+            ]
+        );
+        assert_eq!(field(&spans[0], "payload"), Some("U8(0)"));
+        assert_eq!(field(&spans[0], "outcome"), Some("true"));
+        assert_eq!(field(&spans[1], "service"), Some("provider"));
+        assert_eq!(field(&spans[1], "is_zero"), Some("true"));
+        assert_eq!(field(&spans[2], "boolean"), Some("true"));
+    }
+
+    /// Every provider span has the translated payload, then the outcome or the error.
+    #[test]
+    fn records_provider_answers() {
+        let mut mock = provider::MockProvider::new();
+        mock.expect_try_functionality()
+            .returning(|_| Err(provider::ProviderError::Unavailable));
+        mock.expect_try_evaluate()
+            .returning(|_, _| Ok(provider::Outcome(true)));
+        mock.expect_functionality_batch()
+            .returning(|payloads| payloads.iter().map(|_| provider::Outcome(false)).collect());
+        let spans = SpanRecorder::record(|| {
+            let adapter = crate::GenericProviderAdapter::new(mock);
+            assert!(adapter.is_zero_value(&Value::I8(-1)).is_err());
+            assert!(adapter.evaluate(Byte(3), &Predicate::Odd).is_ok());
+            assert!(adapter.is_zero_many(&[Byte(1), Byte(2)]).is_ok());
+        });
+        let fields: Vec<_> = spans
+            .iter()
+            .map(|span| {
+                (
+                    span.name,
+                    field(span, "payload"),
+                    field(span, "outcome"),
+                    field(span, "error"),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (
+                    "provider.try_functionality",
+                    Some("I8(-1)"),
+                    None,
+                    Some("provider unavailable")
+                ),
+                ("provider.try_evaluate", Some("U8(3)"), Some("true"), None),
+                (
+                    "provider.functionality_batch",
+                    Some("[U8(1), U8(2)]"),
+                    Some("[false, false]"),
+                    None
+                ),
+            ]
+        );
+    }
+
+    /// The in-process application only traces its calls when asked to.
+    #[test]
+    fn traces_in_process_when_asked() {
+        for tracing in [false, true] {
+            let spans = SpanRecorder::record(|| {
+                let mut builder = ApplicationBuilder::new();
+                if tracing {
+                    builder = builder.tracing();
+                }
+                let app = builder.build_in_process().unwrap();
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                let is_zero =
+                    runtime.block_on(crate::AsyncByteService::is_zero(&app.byte_service, Byte(0)));
+                assert_eq!(is_zero, Ok(Boolean(true)));
+            });
+            let traced = spans.iter().any(|span| span.name == "byte_service.is_zero");
+            assert_eq!(traced, tracing);
+        }
+    }
+
+    #[test]
+    fn records_errors() {
+        let mut mock = crate::MockByteService::new();
+        mock.expect_is_zero()
+            .returning(|_| Err(ByteServiceError::Unavailable));
+        let spans = SpanRecorder::record(|| {
+            let service = TracingByteService::new(mock);
+            assert!(service.is_zero(Byte(1)).is_err());
+        });
+        assert_eq!(spans.len(), 1);
+        assert_eq!(field(&spans[0], "byte"), Some("1"));
+        assert_eq!(field(&spans[0], "error"), Some("service unavailable"));
+        assert_eq!(field(&spans[0], "is_zero"), None);
+    }
+
+    #[test]
+    fn traces_value_calls() {
+        let mut mock = crate::MockByteService::new();
+        mock.expect_is_zero_value()
+            .times(1)
+            .returning(|_| Ok(Boolean(false)));
+        let spans = SpanRecorder::record(|| {
+            let service = TracingByteService::named(mock, "mock");
+            assert_eq!(service.is_zero_value(&Value::U16(256)), Ok(Boolean(false)));
+        });
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "byte_service.is_zero_value");
+        assert_eq!(field(&spans[0], "value"), Some("U16(256)"));
+        assert_eq!(field(&spans[0], "is_zero"), Some("false"));
+    }
+}
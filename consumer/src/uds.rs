@@ -0,0 +1,121 @@
+//! ByteService backed by a provider running next to this process,
+//! e.g. as a sidecar, reached over a Unix socket with the binary protocol
+//! of `provider::frame`, as served by `provider-server --uds PATH`.
+
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::framed::{FramedClient, Transport};
+
+/// Adapter calling a local provider over a single connection,
+/// like `TcpProviderAdapter`.
+pub type UdsProviderAdapter = FramedClient<UdsTransport>;
+
+/// Connections of a `UdsProviderAdapter`.
+pub struct UdsTransport {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl UdsProviderAdapter {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Calls the provider listening on the socket at `path`, with the default timeout.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_timeout(path, Self::DEFAULT_TIMEOUT)
+    }
+
+    /// `timeout` bounds a whole call. Connecting to a local socket does not wait.
+    pub fn with_timeout(path: impl Into<PathBuf>, timeout: Duration) -> Self {
+        let transport = UdsTransport {
+            path: path.into(),
+            timeout,
+        };
+        Self::with_transport(transport, timeout)
+    }
+}
+
+impl Transport for UdsTransport {
+    type Stream = UnixStream;
+
+    fn connect(&self) -> io::Result<(UnixStream, UnixStream)> {
+        let stream = UnixStream::connect(&self.path)?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        Ok((stream.try_clone()?, stream))
+    }
+
+    fn shutdown(stream: &UnixStream) {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boolean, Byte, ByteService, ByteServiceError, Value};
+    use provider::{DefaultProvider, MockProvider, Outcome, Payload, ProviderError};
+    use provider_server::SharedProvider;
+    use std::path::Path;
+    use std::sync::Arc;
+    use test_support::{BackgroundServer, TempDir};
+
+    /// Runs the binary front of `provider-server` in-process until the server is dropped.
+    fn start(provider: SharedProvider, path: &Path) -> BackgroundServer {
+        BackgroundServer::uds(path, move |listener, shutdown| {
+            provider_server::framed::serve_uds(listener, provider, shutdown)
+        })
+    }
+
+    #[test]
+    fn calls_the_provider() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("provider.sock");
+        let _server = start(Arc::new(DefaultProvider), &path);
+        let adapter = UdsProviderAdapter::new(&path);
+        assert_eq!(adapter.is_zero(Byte(0)), Ok(Boolean(true)));
+        assert_eq!(adapter.is_zero(Byte(1)), Ok(Boolean(false)));
+        assert_eq!(
+            adapter.is_zero_many(&[Byte(3), Byte(0)]),
+            Ok(vec![Boolean(false), Boolean(true)])
+        );
+        assert_eq!(adapter.is_zero_value(&Value::U32(0)), Ok(Boolean(true)));
+        let calls: Vec<_> = (0..4u8)
+            .map(|value| {
+                let adapter = adapter.clone();
+                std::thread::spawn(move || adapter.is_zero(Byte(value)))
+            })
+            .collect();
+        for (value, call) in calls.into_iter().enumerate() {
+            assert_eq!(call.join().unwrap(), Ok(Boolean(value == 0)));
+        }
+    }
+
+    #[test]
+    fn converts_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("provider.sock");
+        let mut provider = MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .returning(|payload| match payload {
+                Payload::U8(1) => Err(ProviderError::Internal("broken".into())),
+                _ => {
+                    std::thread::sleep(Duration::from_millis(300));
+                    Ok(Outcome(true))
+                }
+            });
+        let _server = start(Arc::new(provider), &path);
+        let adapter = UdsProviderAdapter::with_timeout(&path, Duration::from_millis(100));
+        assert_eq!(
+            adapter.is_zero(Byte(1)),
+            Err(ByteServiceError::Internal("broken".into()))
+        );
+        assert_eq!(adapter.is_zero(Byte(0)), Err(ByteServiceError::Timeout));
+
+        let missing = UdsProviderAdapter::new(dir.path().join("missing.sock"));
+        assert_eq!(missing.is_zero(Byte(0)), Err(ByteServiceError::Unavailable));
+    }
+}
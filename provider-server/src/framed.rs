@@ -0,0 +1,357 @@
+//! Binary front of the provider, speaking the frames of `provider::frame`
+//! over TCP or Unix sockets, for callers to whom a request per HTTP call is too heavy.
+//! Requests of a connection are answered as soon as each completes,
+//! so clients can pipeline them and match answers by ID.
+
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use provider::frame::{self, Request, Response};
+use provider::ProviderError;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, watch, Semaphore};
+
+use crate::SharedProvider;
+
+/// Requests of one connection answered at once. At the limit, the connection is
+/// not read until one completes, so a client cannot queue work faster than it is done.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Serves `provider` on `listener` until `shutdown` completes.
+/// Open connections then stop reading requests, answer those in flight, and close.
+pub async fn serve_tcp(
+    listener: TcpListener,
+    provider: SharedProvider,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    serve(listener, provider, shutdown).await
+}
+
+/// Serves `provider` on `listener`, e.g. to a sidecar, like `serve_tcp`.
+#[cfg(unix)]
+pub async fn serve_uds(
+    listener: UnixListener,
+    provider: SharedProvider,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    serve(listener, provider, shutdown).await
+}
+
+/// What `serve` needs of a listener.
+trait Listener {
+    type Stream: AsyncRead + AsyncWrite + Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = io::Result<Self::Stream>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<TcpStream> {
+        let (stream, _) = TcpListener::accept(self).await?;
+        // Only latency suffers without it, so the connection is served anyway.
+        if let Err(error) = stream.set_nodelay(true) {
+            tracing::warn!(error = %error, "cannot disable Nagle's algorithm");
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    async fn accept(&self) -> io::Result<UnixStream> {
+        let (stream, _) = UnixListener::accept(self).await?;
+        Ok(stream)
+    }
+}
+
+async fn serve(
+    listener: impl Listener,
+    provider: SharedProvider,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let connections = Connections::new(provider);
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok(stream) => connections.spawn(stream),
+                Err(error) => accept_failed(error).await,
+            },
+        }
+    }
+    connections.close().await;
+    Ok(())
+}
+
+/// Connections being served, and what they need to shut down.
+struct Connections {
+    provider: SharedProvider,
+    stop: watch::Sender<bool>,
+    // Every connection holds a sender, so receiving None means they all closed.
+    open: mpsc::Sender<()>,
+    closed: mpsc::Receiver<()>,
+}
+
+impl Connections {
+    fn new(provider: SharedProvider) -> Self {
+        let (open, closed) = mpsc::channel(1);
+        Self {
+            provider,
+            stop: watch::channel(false).0,
+            open,
+            closed,
+        }
+    }
+
+    fn spawn<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let provider = Arc::clone(&self.provider);
+        let stopped = self.stop.subscribe();
+        let open = self.open.clone();
+        tokio::spawn(async move {
+            serve_connection(stream, provider, stopped).await;
+            drop(open);
+        });
+    }
+
+    async fn close(mut self) {
+        tracing::info!("shutting down");
+        self.stop.send_replace(true);
+        drop(self.open);
+        let _ = self.closed.recv().await;
+    }
+}
+
+/// Keeps accepting after failures such as running out of file descriptors,
+/// without spinning while they last.
+async fn accept_failed(error: io::Error) {
+    tracing::warn!(error = %error, "cannot accept a connection");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    provider: SharedProvider,
+    mut stopped: watch::Receiver<bool>,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (responses, pending) = mpsc::channel(MAX_IN_FLIGHT);
+    let writing = tokio::spawn(write_responses(writer, pending));
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+    loop {
+        // Held until the request is answered.
+        let permit = tokio::select! {
+            permit = Arc::clone(&in_flight).acquire_owned() => permit.expect("never closed"),
+            _ = stopped.changed() => break,
+        };
+        let frame = tokio::select! {
+            frame = read_frame(&mut reader) => frame,
+            _ = stopped.changed() => break,
+        };
+        let frame = match frame {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(error) => {
+                tracing::warn!(error = %error, "closing connection");
+                break;
+            }
+        };
+        let request = match Request::decode(&frame) {
+            Ok(request) => request,
+            Err(error) => match frame::frame_id(&frame) {
+                Some(id) => {
+                    let result = Err(ProviderError::InvalidPayload(error.to_string()));
+                    if responses.send(Response { id, result }).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                None => {
+                    tracing::warn!(error = %error, "closing connection");
+                    break;
+                }
+            },
+        };
+        let provider = Arc::clone(&provider);
+        let responses = responses.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let start = Instant::now();
+            let result = provider.try_functionality(request.payload);
+            tracing::debug!(
+                id = request.id,
+                ok = result.is_ok(),
+                elapsed_us = start.elapsed().as_micros() as u64,
+                "request"
+            );
+            let _ = responses.blocking_send(Response {
+                id: request.id,
+                result,
+            });
+        });
+    }
+    // The writer ends once the requests in flight, which hold the other senders, are answered.
+    drop(responses);
+    if let Ok(Err(error)) = writing.await {
+        tracing::warn!(error = %error, "cannot answer");
+    }
+}
+
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let mut frame = vec![0; frame::check_len(len)?];
+    reader.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+/// Writes answers as they come, flushing whenever none is waiting.
+async fn write_responses(
+    writer: impl AsyncWrite + Unpin,
+    mut pending: mpsc::Receiver<Response>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    while let Some(response) = pending.recv().await {
+        writer.write_all(&encode(response)?).await?;
+        while let Ok(response) = pending.try_recv() {
+            writer.write_all(&encode(response)?).await?;
+        }
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+/// Answers that do not fit in a frame, with their long error message, become internal errors.
+fn encode(response: Response) -> io::Result<Vec<u8>> {
+    response.encode().or_else(|error| {
+        Response {
+            id: response.id,
+            result: Err(ProviderError::Internal(error.to_string())),
+        }
+        .encode()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider::{MockProvider, Outcome, Payload};
+    use std::net::SocketAddr;
+    use test_support::SlowProvider;
+    use tokio::sync::oneshot;
+
+    async fn start(provider: SharedProvider) -> (SocketAddr, oneshot::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+        tokio::spawn(serve_tcp(listener, provider, async {
+            let _ = stopped.await;
+        }));
+        (address, stop)
+    }
+
+    async fn receive(stream: &mut TcpStream) -> Response {
+        let frame = read_frame(stream).await.unwrap().unwrap();
+        Response::decode(&frame).unwrap()
+    }
+
+    /// Pipelined requests are answered as each completes, not in order.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn answers_pipelined_requests() {
+        let provider = SlowProvider::new(Duration::from_millis(200)).only(Payload::U8(0));
+        let (address, _stop) = start(Arc::new(provider)).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut requests = Vec::new();
+        for (id, payload) in [
+            (7, Payload::U8(0)),
+            (8, Payload::I64(-1)),
+            (9, Payload::Bytes(vec![0])),
+        ] {
+            requests.extend(Request { id, payload }.encode().unwrap());
+        }
+        // Unknown payload types are answered with an error.
+        requests.extend([0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 10, 0x01, 42]);
+        stream.write_all(&requests).await.unwrap();
+
+        let mut responses = Vec::new();
+        for _ in 0..4 {
+            responses.push(receive(&mut stream).await);
+        }
+        assert_eq!(
+            responses.last(),
+            Some(&Response {
+                id: 7,
+                result: Ok(Outcome(true))
+            })
+        );
+        responses.sort_by_key(|response| response.id);
+        assert_eq!(responses[1].result, Ok(Outcome(false)));
+        assert_eq!(responses[2].result, Ok(Outcome(true)));
+        assert!(matches!(
+            responses[3],
+            Response {
+                id: 10,
+                result: Err(ProviderError::InvalidPayload(_))
+            }
+        ));
+    }
+
+    /// A client pipelining more requests than the limit only gets that many running.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bounds_requests_in_flight() {
+        let provider = Arc::new(SlowProvider::new(Duration::from_millis(100)));
+        let (address, _stop) = start(Arc::clone(&provider) as SharedProvider).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let count = MAX_IN_FLIGHT as u64 + 16;
+        let mut requests = Vec::new();
+        for id in 0..count {
+            let payload = Payload::U8(0);
+            requests.extend(Request { id, payload }.encode().unwrap());
+        }
+        stream.write_all(&requests).await.unwrap();
+        for _ in 0..count {
+            assert_eq!(receive(&mut stream).await.result, Ok(Outcome(true)));
+        }
+        // How many overlap depends on scheduling, but never more than the limit.
+        let most_running = provider.most_running();
+        assert!(most_running > 1, "{}", most_running);
+        assert!(most_running <= MAX_IN_FLIGHT, "{}", most_running);
+    }
+
+    /// On shutdown, requests in flight are still answered before connections close.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drains_connections_on_shutdown() {
+        let mut provider = MockProvider::new();
+        provider.expect_try_functionality().returning(|_| {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(Outcome(true))
+        });
+        let (address, stop) = start(Arc::new(provider)).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = Request {
+            id: 1,
+            payload: Payload::U8(0),
+        };
+        stream.write_all(&request.encode().unwrap()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop.send(()).unwrap();
+        assert_eq!(receive(&mut stream).await.result, Ok(Outcome(true)));
+        assert_eq!(read_frame(&mut stream).await.unwrap(), None);
+    }
+}
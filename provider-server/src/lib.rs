@@ -0,0 +1,293 @@
+//! HTTP front of the provider, so that it can run as a separate process.
+//! The routes and bodies are described in `provider::wire`; besides them,
+//! `GET /health` answers 200 while the process runs, and `GET /ready`
+//! answers 200 until shutdown starts, then 503 while in-flight requests drain.
+
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use provider::wire::{
+    self, BatchRequest, BatchResponse, ErrorResponse, FunctionalityRequest, FunctionalityResponse,
+};
+use provider::{Provider, ProviderError};
+use tokio::net::TcpListener;
+use tokio::task::JoinError;
+
+pub mod framed;
+
+/// Provider answering the requests, shared by every connection.
+pub type SharedProvider = Arc<dyn Provider + Send + Sync>;
+
+#[derive(Clone)]
+struct AppState {
+    provider: SharedProvider,
+    ready: Arc<AtomicBool>,
+}
+
+/// Routes of the server. `ready` is what `GET /ready` reports.
+pub fn router(provider: SharedProvider, ready: Arc<AtomicBool>) -> Router {
+    Router::new()
+        .route(wire::FUNCTIONALITY_PATH, post(functionality))
+        .route(wire::FUNCTIONALITY_BATCH_PATH, post(functionality_batch))
+        .route(wire::HEALTH_PATH, get(health))
+        .route(wire::READY_PATH, get(readiness))
+        .layer(middleware::from_fn(log_request))
+        .with_state(AppState { provider, ready })
+}
+
+/// Serves `provider` on `listener` until `shutdown` completes.
+/// Readiness then fails for `drain`, so that load balancers stop sending
+/// requests, before the listener closes and in-flight requests complete.
+pub async fn serve(
+    listener: TcpListener,
+    provider: SharedProvider,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+    drain: Duration,
+) -> io::Result<()> {
+    let ready = Arc::new(AtomicBool::new(true));
+    let app = router(provider, Arc::clone(&ready));
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            shutdown.await;
+            tracing::info!(drain_ms = drain.as_millis() as u64, "shutting down");
+            ready.store(false, Ordering::SeqCst);
+            tokio::time::sleep(drain).await;
+        })
+        .await
+}
+
+/// Completes on Ctrl-C, or on SIGTERM on Unix.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+async fn functionality(
+    State(state): State<AppState>,
+    body: Result<Json<FunctionalityRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return error_response(StatusCode::BAD_REQUEST, rejection.body_text()),
+    };
+    let provider = Arc::clone(&state.provider);
+    match tokio::task::spawn_blocking(move || provider.try_functionality(request.payload)).await {
+        Ok(Ok(outcome)) => Json(FunctionalityResponse { outcome }).into_response(),
+        Ok(Err(error)) => provider_error_response(error),
+        Err(error) => join_error_response(error),
+    }
+}
+
+async fn functionality_batch(
+    State(state): State<AppState>,
+    body: Result<Json<BatchRequest>, JsonRejection>,
+) -> Response {
+    let request = match body {
+        Ok(Json(request)) => request,
+        Err(rejection) => return error_response(StatusCode::BAD_REQUEST, rejection.body_text()),
+    };
+    let provider = Arc::clone(&state.provider);
+    match tokio::task::spawn_blocking(move || provider.functionality_batch(&request.payloads)).await
+    {
+        Ok(outcomes) => Json(BatchResponse { outcomes }).into_response(),
+        Err(error) => join_error_response(error),
+    }
+}
+
+async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+async fn readiness(State(state): State<AppState>) -> StatusCode {
+    if state.ready.load(Ordering::SeqCst) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+fn provider_error_response(error: ProviderError) -> Response {
+    let status = StatusCode::from_u16(wire::error_status(&error))
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, Json(ErrorResponse::from(&error))).into_response()
+}
+
+/// The provider call panicked, or was cancelled by the shutdown of the runtime.
+fn join_error_response(error: JoinError) -> Response {
+    tracing::error!(error = %error, "provider call failed");
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+}
+
+fn error_response(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorResponse { error, kind: None })).into_response()
+}
+
+/// Logs every request once answered, with its status and duration.
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    tracing::info!(
+        method = %method,
+        path,
+        status = response.status().as_u16(),
+        elapsed_us = start.elapsed().as_micros() as u64,
+        "request"
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider::{DefaultProvider, MockProvider};
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+
+    /// Starts a server on an ephemeral port, stopped by sending on the returned channel.
+    async fn start(
+        provider: SharedProvider,
+        drain: Duration,
+    ) -> (
+        SocketAddr,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<io::Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = oneshot::channel();
+        let server = tokio::spawn(serve(
+            listener,
+            provider,
+            async {
+                let _ = stopped.await;
+            },
+            drain,
+        ));
+        (address, stop, server)
+    }
+
+    /// Sends one request, and returns the status and the body of the response.
+    async fn send(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, body.to_string())
+    }
+
+    #[tokio::test]
+    async fn serves_functionality() {
+        let (address, _stop, _server) = start(Arc::new(DefaultProvider), Duration::ZERO).await;
+        assert_eq!(
+            send(
+                address,
+                "POST",
+                "/v1/functionality",
+                r#"{"payload":{"type":"u8","value":0}}"#
+            )
+            .await,
+            (200, r#"{"outcome":true}"#.to_string())
+        );
+        assert_eq!(
+            send(
+                address,
+                "POST",
+                "/v1/functionality/batch",
+                r#"{"payloads":[{"type":"u16","value":256},{"type":"bytes","value":[0,0]}]}"#
+            )
+            .await,
+            (200, r#"{"outcomes":[false,true]}"#.to_string())
+        );
+        assert_eq!(send(address, "GET", "/health", "").await.0, 200);
+        assert_eq!(send(address, "GET", "/ready", "").await.0, 200);
+    }
+
+    #[tokio::test]
+    async fn answers_errors() {
+        let mut provider = MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .times(1)
+            .returning(|_| Err(ProviderError::Timeout));
+        let (address, _stop, _server) = start(Arc::new(provider), Duration::ZERO).await;
+        let body = r#"{"payload":{"type":"u8","value":1}}"#;
+        assert_eq!(
+            send(address, "POST", "/v1/functionality", body).await,
+            (
+                504,
+                r#"{"error":"provider timed out","kind":"timeout"}"#.to_string()
+            )
+        );
+        let (status, body) = send(address, "POST", "/v1/functionality", "{").await;
+        assert_eq!(status, 400);
+        assert!(serde_json::from_str::<ErrorResponse>(&body).is_ok());
+    }
+
+    #[tokio::test]
+    async fn answers_panics_with_internal_errors() {
+        let mut provider = MockProvider::new();
+        provider
+            .expect_try_functionality()
+            .times(1)
+            .returning(|_| panic!("provider panicked"));
+        let (address, _stop, _server) = start(Arc::new(provider), Duration::ZERO).await;
+        let body = r#"{"payload":{"type":"u8","value":1}}"#;
+        let (status, body) = send(address, "POST", "/v1/functionality", body).await;
+        assert_eq!(status, 500);
+        assert!(serde_json::from_str::<ErrorResponse>(&body).is_ok());
+        assert_eq!(send(address, "GET", "/health", "").await.0, 200);
+    }
+
+    /// Readiness fails while draining, then the server stops.
+    #[tokio::test]
+    async fn shuts_down_gracefully() {
+        let (address, stop, server) =
+            start(Arc::new(DefaultProvider), Duration::from_millis(300)).await;
+        stop.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(send(address, "GET", "/ready", "").await.0, 503);
+        assert_eq!(send(address, "GET", "/health", "").await.0, 200);
+        server.await.unwrap().unwrap();
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+}
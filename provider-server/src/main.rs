@@ -0,0 +1,186 @@
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use provider::DefaultProvider;
+use provider_server::framed::serve_tcp;
+#[cfg(unix)]
+use provider_server::framed::serve_uds;
+use provider_server::{serve, shutdown_signal, SharedProvider};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tracing_subscriber::EnvFilter;
+
+const USAGE: &str = "\
+Usage: provider-server [--listen ADDRESS] [--tcp ADDRESS] [--uds PATH]
+                       [--drain SECONDS]
+
+Serves the provider over HTTP, and optionally over the binary protocol,
+until Ctrl-C or SIGTERM.
+
+Options:
+  --listen ADDRESS   Address to listen on (default: 127.0.0.1:8080).
+  --tcp ADDRESS      Address to also serve the binary protocol on.
+  --uds PATH         Unix socket to also serve the binary protocol on,
+                     replacing any socket left at PATH by a previous run.
+  --drain SECONDS    Time readiness fails before shutting down (default: 5).
+  -h, --help         Print this help.
+
+Logs go to stderr; set RUST_LOG to change their verbosity (default: info).";
+
+struct Options {
+    listen: SocketAddr,
+    tcp: Option<SocketAddr>,
+    #[cfg(unix)]
+    uds: Option<PathBuf>,
+    drain: Duration,
+}
+
+/// Returns None when help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+        tcp: None,
+        #[cfg(unix)]
+        uds: None,
+        drain: Duration::from_secs(5),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--listen" => {
+                let address = value("--listen")?;
+                options.listen = parse_address(&address)?;
+            }
+            "--tcp" => {
+                let address = value("--tcp")?;
+                options.tcp = Some(parse_address(&address)?);
+            }
+            #[cfg(unix)]
+            "--uds" => options.uds = Some(value("--uds")?.into()),
+            "--drain" => {
+                let seconds = value("--drain")?;
+                options.drain = seconds
+                    .parse()
+                    .map(Duration::from_secs)
+                    .map_err(|_| format!("invalid number of seconds `{}`", seconds))?;
+            }
+            _ => return Err(format!("unknown option `{}`", arg)),
+        }
+    }
+    Ok(Some(options))
+}
+
+fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    address
+        .parse()
+        .map_err(|_| format!("invalid address `{}`", address))
+}
+
+/// Bin entrypoint.
+/// Exits with 2 on usage errors, and with 1 when the server cannot run.
+#[tokio::main]
+async fn main() -> ExitCode {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("Error: {}.\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let provider: SharedProvider = Arc::new(DefaultProvider);
+    let Some(listener) = bind(options.listen, "http").await else {
+        return ExitCode::FAILURE;
+    };
+    let tcp_listener = match options.tcp {
+        Some(address) => match bind(address, "tcp").await {
+            Some(listener) => Some(listener),
+            None => return ExitCode::FAILURE,
+        },
+        None => None,
+    };
+    #[cfg(unix)]
+    let uds_listener = match &options.uds {
+        Some(path) => match bind_uds(path) {
+            Some(listener) => Some(listener),
+            None => return ExitCode::FAILURE,
+        },
+        None => None,
+    };
+    let http = serve(
+        listener,
+        Arc::clone(&provider),
+        shutdown_signal(),
+        options.drain,
+    );
+    let tcp = async {
+        match tcp_listener {
+            Some(listener) => serve_tcp(listener, Arc::clone(&provider), shutdown_signal()).await,
+            None => Ok(()),
+        }
+    };
+    let uds = async {
+        #[cfg(unix)]
+        if let (Some(listener), Some(path)) = (uds_listener, &options.uds) {
+            let result = serve_uds(listener, Arc::clone(&provider), shutdown_signal()).await;
+            let _ = std::fs::remove_file(path);
+            return result;
+        }
+        Ok::<(), io::Error>(())
+    };
+    match tokio::try_join!(http, tcp, uds) {
+        Ok(((), (), ())) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}.", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn bind(address: SocketAddr, protocol: &str) -> Option<TcpListener> {
+    match TcpListener::bind(address).await {
+        Ok(listener) => {
+            tracing::info!(address = %address, protocol, "listening");
+            Some(listener)
+        }
+        Err(error) => {
+            eprintln!("Error: cannot listen on {}: {}.", address, error);
+            None
+        }
+    }
+}
+
+/// Replaces a socket left behind by a previous run, but no other kind of file.
+#[cfg(unix)]
+fn bind_uds(path: &Path) -> Option<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+    match UnixListener::bind(path) {
+        Ok(listener) => {
+            tracing::info!(path = %path.display(), protocol = "uds", "listening");
+            Some(listener)
+        }
+        Err(error) => {
+            eprintln!("Error: cannot listen on {}: {}.", path.display(), error);
+            None
+        }
+    }
+}
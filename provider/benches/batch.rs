@@ -0,0 +1,26 @@
+//! Batch functionality against calling `functionality` on each payload.
+//! Run with `cargo bench -p provider`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use provider::{functionality, functionality_batch, Payload};
+
+fn batch(c: &mut Criterion) {
+    let payloads: Vec<Payload> = (0..4096u32).map(|value| Payload::U8(value as u8)).collect();
+    let mut group = c.benchmark_group("batch");
+    group.bench_function("one by one", |b| {
+        b.iter(|| {
+            black_box(&payloads)
+                .iter()
+                .map(|payload| functionality(payload.clone()))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("batch", |b| {
+        b.iter(|| functionality_batch(black_box(&payloads)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
@@ -0,0 +1,352 @@
+//! Compact binary framing of provider calls, for transports where HTTP/JSON is too heavy.
+//!
+//! Every frame is a big-endian u32 length, followed by that many bytes:
+//! a big-endian u64 request ID, a kind byte, then the body of that kind.
+//! IDs are chosen by clients and echoed in responses, so that requests can be
+//! pipelined over one connection and answered in any order.
+//!
+//! | kind   | frame    | body                                                |
+//! |--------|----------|-----------------------------------------------------|
+//! | `0x01` | request  | payload type byte, then the value                   |
+//! | `0x81` | outcome  | `0` or `1`                                          |
+//! | `0x82` | error    | error code byte, then the UTF-8 message             |
+//!
+//! Payload types are `0` to `7` for u8, u16, u32, u64, i8, i16, i32 and i64,
+//! followed by the big-endian value, and `8` for bytes, followed by all of them.
+//! Error codes are `0` to `3` for InvalidPayload, Unavailable, Timeout and Internal.
+
+use std::io::{self, Read};
+
+use crate::{Outcome, Payload, ProviderError};
+
+/// Longest accepted frame, length prefix excluded.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+const REQUEST: u8 = 0x01;
+const OUTCOME: u8 = 0x81;
+const ERROR: u8 = 0x82;
+
+/// A call of `try_functionality`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub id: u64,
+    pub payload: Payload,
+}
+
+/// The answer to the request with the same ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub id: u64,
+    pub result: Result<Outcome, ProviderError>,
+}
+
+impl Request {
+    /// The whole frame, length prefix included.
+    /// Fails with `InvalidInput` when it is longer than `MAX_FRAME_LEN`.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        match &self.payload {
+            Payload::U8(value) => encode_value(&mut body, 0, &value.to_be_bytes()),
+            Payload::U16(value) => encode_value(&mut body, 1, &value.to_be_bytes()),
+            Payload::U32(value) => encode_value(&mut body, 2, &value.to_be_bytes()),
+            Payload::U64(value) => encode_value(&mut body, 3, &value.to_be_bytes()),
+            Payload::I8(value) => encode_value(&mut body, 4, &value.to_be_bytes()),
+            Payload::I16(value) => encode_value(&mut body, 5, &value.to_be_bytes()),
+            Payload::I32(value) => encode_value(&mut body, 6, &value.to_be_bytes()),
+            Payload::I64(value) => encode_value(&mut body, 7, &value.to_be_bytes()),
+            Payload::Bytes(bytes) => encode_value(&mut body, 8, bytes),
+        }
+        encode_frame(self.id, REQUEST, &body)
+    }
+
+    /// Decodes a frame, length prefix excluded.
+    pub fn decode(frame: &[u8]) -> io::Result<Self> {
+        let (id, kind, body) = split_frame(frame)?;
+        if kind != REQUEST {
+            return Err(invalid(format!("unexpected frame kind {:#04x}", kind)));
+        }
+        let (&payload_type, value) = body.split_first().ok_or_else(|| invalid("empty request"))?;
+        let payload = match payload_type {
+            0 => Payload::U8(u8::from_be_bytes(fixed(value)?)),
+            1 => Payload::U16(u16::from_be_bytes(fixed(value)?)),
+            2 => Payload::U32(u32::from_be_bytes(fixed(value)?)),
+            3 => Payload::U64(u64::from_be_bytes(fixed(value)?)),
+            4 => Payload::I8(i8::from_be_bytes(fixed(value)?)),
+            5 => Payload::I16(i16::from_be_bytes(fixed(value)?)),
+            6 => Payload::I32(i32::from_be_bytes(fixed(value)?)),
+            7 => Payload::I64(i64::from_be_bytes(fixed(value)?)),
+            8 => Payload::Bytes(value.to_vec()),
+            _ => return Err(invalid(format!("unknown payload type {}", payload_type))),
+        };
+        Ok(Self { id, payload })
+    }
+}
+
+impl Response {
+    /// The whole frame, length prefix included.
+    /// Fails with `InvalidInput` when it is longer than `MAX_FRAME_LEN`.
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        match &self.result {
+            Ok(outcome) => encode_frame(self.id, OUTCOME, &[u8::from(outcome.0)]),
+            Err(error) => {
+                let (code, message) = match error {
+                    ProviderError::InvalidPayload(reason) => (0, reason.as_str()),
+                    ProviderError::Unavailable => (1, ""),
+                    ProviderError::Timeout => (2, ""),
+                    ProviderError::Internal(reason) => (3, reason.as_str()),
+                };
+                let mut body = vec![code];
+                body.extend_from_slice(message.as_bytes());
+                encode_frame(self.id, ERROR, &body)
+            }
+        }
+    }
+
+    /// Decodes a frame, length prefix excluded.
+    pub fn decode(frame: &[u8]) -> io::Result<Self> {
+        let (id, kind, body) = split_frame(frame)?;
+        let result = match (kind, body) {
+            (OUTCOME, [0]) => Ok(Outcome(false)),
+            (OUTCOME, [1]) => Ok(Outcome(true)),
+            (ERROR, [code, message @ ..]) => {
+                let message = String::from_utf8_lossy(message).into_owned();
+                Err(match code {
+                    0 => ProviderError::InvalidPayload(message),
+                    1 => ProviderError::Unavailable,
+                    2 => ProviderError::Timeout,
+                    3 => ProviderError::Internal(message),
+                    _ => return Err(invalid(format!("unknown error code {}", code))),
+                })
+            }
+            _ => return Err(invalid(format!("invalid response of kind {:#04x}", kind))),
+        };
+        Ok(Self { id, result })
+    }
+}
+
+/// ID of a frame, length prefix excluded, even if the rest cannot be decoded,
+/// so that servers can answer malformed requests.
+pub fn frame_id(frame: &[u8]) -> Option<u64> {
+    frame
+        .get(..8)
+        .map(|id| u64::from_be_bytes(id.try_into().unwrap()))
+}
+
+/// Checks the length prefix of a frame.
+pub fn check_len(len: u32) -> io::Result<usize> {
+    if len > MAX_FRAME_LEN {
+        return Err(invalid(format!("frame of {} bytes is too long", len)));
+    }
+    Ok(len as usize)
+}
+
+/// Reads a frame, without its length prefix.
+/// Returns None when the stream ends between frames.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let mut frame = vec![0; check_len(u32::from_be_bytes(len))?];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+fn encode_value(body: &mut Vec<u8>, payload_type: u8, value: &[u8]) {
+    body.push(payload_type);
+    body.extend_from_slice(value);
+}
+
+fn encode_frame(id: u64, kind: u8, body: &[u8]) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(8 + 1 + body.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame of {} bytes is too long", 8 + 1 + body.len()),
+            )
+        })?;
+    let mut frame = Vec::with_capacity(4 + len as usize);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.push(kind);
+    frame.extend_from_slice(body);
+    Ok(frame)
+}
+
+fn split_frame(frame: &[u8]) -> io::Result<(u64, u8, &[u8])> {
+    let id = frame_id(frame).ok_or_else(|| invalid("truncated frame"))?;
+    let (&kind, body) = frame[8..]
+        .split_first()
+        .ok_or_else(|| invalid("truncated frame"))?;
+    Ok((id, kind, body))
+}
+
+fn fixed<const N: usize>(value: &[u8]) -> io::Result<[u8; N]> {
+    value
+        .try_into()
+        .map_err(|_| invalid(format!("expected {} bytes, got {}", N, value.len())))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the one frame of `bytes`, without its length prefix.
+    fn unframe(bytes: &[u8]) -> Vec<u8> {
+        let mut reader = bytes;
+        let frame = read_frame(&mut reader).unwrap().unwrap();
+        assert!(reader.is_empty());
+        frame
+    }
+
+    fn message(error: io::Error) -> String {
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        error.to_string()
+    }
+
+    #[test]
+    fn round_trips_requests() {
+        let payloads = [
+            Payload::U8(u8::MAX),
+            Payload::U16(256),
+            Payload::U32(u32::MAX - 1),
+            Payload::U64(1 << 40),
+            Payload::I8(i8::MIN),
+            Payload::I16(-2),
+            Payload::I32(i32::MAX),
+            Payload::I64(-1 << 40),
+            Payload::Bytes(vec![]),
+            Payload::Bytes(vec![0, 1, 255]),
+        ];
+        for (id, payload) in payloads.into_iter().enumerate() {
+            let request = Request {
+                id: u64::MAX - id as u64,
+                payload,
+            };
+            assert_eq!(
+                Request::decode(&unframe(&request.encode().unwrap())).unwrap(),
+                request
+            );
+        }
+        assert_eq!(
+            Request {
+                id: 1,
+                payload: Payload::U16(0x0102)
+            }
+            .encode()
+            .unwrap(),
+            [0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 1, 0x01, 1, 1, 2]
+        );
+    }
+
+    #[test]
+    fn round_trips_responses() {
+        let results = [
+            Ok(Outcome(true)),
+            Ok(Outcome(false)),
+            Err(ProviderError::InvalidPayload("odd".into())),
+            Err(ProviderError::Unavailable),
+            Err(ProviderError::Timeout),
+            Err(ProviderError::Internal("broken".into())),
+        ];
+        for (id, result) in results.into_iter().enumerate() {
+            let response = Response {
+                id: id as u64,
+                result,
+            };
+            assert_eq!(
+                Response::decode(&unframe(&response.encode().unwrap())).unwrap(),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert_eq!(
+            message(Request::decode(&[0; 8]).unwrap_err()),
+            "truncated frame"
+        );
+        assert_eq!(
+            message(Response::decode(&[0; 3]).unwrap_err()),
+            "truncated frame"
+        );
+        assert_eq!(frame_id(&[0; 7]), None);
+        assert_eq!(frame_id(&[0, 0, 0, 0, 0, 0, 0, 9, 0x7f]), Some(9));
+        assert_eq!(
+            message(Request::decode(&[0, 0, 0, 0, 0, 0, 0, 9, 0x7f, 0, 0]).unwrap_err()),
+            "unexpected frame kind 0x7f"
+        );
+        assert_eq!(
+            message(Response::decode(&[0, 0, 0, 0, 0, 0, 0, 9, 0x7f, 0]).unwrap_err()),
+            "invalid response of kind 0x7f"
+        );
+        assert_eq!(
+            message(Response::decode(&[0, 0, 0, 0, 0, 0, 0, 9, OUTCOME, 2]).unwrap_err()),
+            "invalid response of kind 0x81"
+        );
+        assert_eq!(
+            message(Response::decode(&[0, 0, 0, 0, 0, 0, 0, 9, ERROR, 4]).unwrap_err()),
+            "unknown error code 4"
+        );
+        // A frame cut short of its length prefix.
+        assert!(read_frame(&mut &[0, 0, 0, 9, 0][..]).is_err());
+        assert_eq!(read_frame(&mut &[][..]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_payloads() {
+        assert_eq!(
+            message(Request::decode(&[0, 0, 0, 0, 0, 0, 0, 9, REQUEST]).unwrap_err()),
+            "empty request"
+        );
+        assert_eq!(
+            message(Request::decode(&[0, 0, 0, 0, 0, 0, 0, 9, REQUEST, 9, 0]).unwrap_err()),
+            "unknown payload type 9"
+        );
+        assert_eq!(
+            message(Request::decode(&[0, 0, 0, 0, 0, 0, 0, 9, REQUEST, 1, 0]).unwrap_err()),
+            "expected 2 bytes, got 1"
+        );
+        assert_eq!(
+            message(Request::decode(&[0, 0, 0, 0, 0, 0, 0, 9, REQUEST, 0, 0, 0]).unwrap_err()),
+            "expected 1 bytes, got 2"
+        );
+    }
+
+    #[test]
+    fn limits_frame_length() {
+        assert_eq!(check_len(MAX_FRAME_LEN).unwrap(), MAX_FRAME_LEN as usize);
+        assert_eq!(
+            message(check_len(MAX_FRAME_LEN + 1).unwrap_err()),
+            "frame of 16777217 bytes is too long"
+        );
+        let mut reader = &(MAX_FRAME_LEN + 1).to_be_bytes()[..];
+        assert_eq!(
+            message(read_frame(&mut reader).unwrap_err()),
+            "frame of 16777217 bytes is too long"
+        );
+
+        // Nine bytes of ID and kind, and the payload type, leave this much for the value.
+        let longest = MAX_FRAME_LEN as usize - 10;
+        let request = |len| Request {
+            id: 0,
+            payload: Payload::Bytes(vec![0; len]),
+        };
+        assert_eq!(
+            request(longest).encode().unwrap().len(),
+            4 + MAX_FRAME_LEN as usize
+        );
+        let error = request(longest + 1).encode().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "frame of 16777217 bytes is too long");
+    }
+}
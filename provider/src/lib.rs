@@ -1,10 +1,258 @@
-/// Provider Input type
-pub struct Payload(pub u8);
+use std::fmt;
+
+pub mod frame;
+pub mod predicate;
+#[cfg(feature = "serde")]
+pub mod wire;
+
+pub use predicate::Predicate;
+
+/// Provider Input type: an integer of any width, or a buffer of bytes.
+/// With the `serde` feature, it is written as the lowercase variant name
+/// and the value, e.g. `{"type":"u16","value":256}` or `{"type":"bytes","value":[0,1]}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value", rename_all = "lowercase")
+)]
+pub enum Payload {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Bytes(Vec<u8>),
+}
+
+impl Payload {
+    /// Whether the payload is zero. A buffer is zero when all of its bytes are,
+    /// so the empty buffer is zero.
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Payload::U8(value) => *value == 0,
+            Payload::U16(value) => *value == 0,
+            Payload::U32(value) => *value == 0,
+            Payload::U64(value) => *value == 0,
+            Payload::I8(value) => *value == 0,
+            Payload::I16(value) => *value == 0,
+            Payload::I32(value) => *value == 0,
+            Payload::I64(value) => *value == 0,
+            Payload::Bytes(bytes) => bytes.iter().all(|byte| *byte == 0),
+        }
+    }
+}
+
+macro_rules! payload_from {
+    ($($variant:ident($type:ty)),*) => {
+        $(
+            impl From<$type> for Payload {
+                fn from(value: $type) -> Self {
+                    Payload::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+payload_from!(
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Bytes(Vec<u8>)
+);
+
+impl From<&[u8]> for Payload {
+    fn from(bytes: &[u8]) -> Self {
+        Payload::Bytes(bytes.to_vec())
+    }
+}
 
 /// Provider Output type
+/// With the `serde` feature, it is written as a bare boolean, e.g. `true`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Outcome(pub bool);
 
+/// Provider Error type.
+/// Describes why the provider could not produce an Outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    /// The payload was rejected by the provider.
+    InvalidPayload(String),
+    /// The provider could not be reached.
+    Unavailable,
+    /// The provider did not answer in time.
+    Timeout,
+    /// The provider failed for an unexpected reason.
+    Internal(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::InvalidPayload(reason) => write!(f, "invalid payload: {}", reason),
+            ProviderError::Unavailable => write!(f, "provider unavailable"),
+            ProviderError::Timeout => write!(f, "provider timed out"),
+            ProviderError::Internal(reason) => write!(f, "provider internal error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
 /// Provider functionality
 pub fn functionality(payload: Payload) -> Outcome {
-    Outcome(payload.0 == 0)
+    evaluate(payload, &Predicate::zero())
+}
+
+/// Provider functionality for any question: `functionality` answers `Predicate::zero()`.
+pub fn evaluate(payload: Payload, predicate: &Predicate) -> Outcome {
+    Outcome(predicate.matches(&payload))
+}
+
+/// Fallible counterpart of `evaluate`, see `try_functionality`.
+pub fn try_evaluate(payload: Payload, predicate: &Predicate) -> Result<Outcome, ProviderError> {
+    Ok(evaluate(payload, predicate))
+}
+
+/// Bytes compared at once by `functionality_batch`.
+const LANES: usize = 16;
+
+/// Batch provider functionality.
+/// Equivalent to calling `functionality` on each payload, without moving them.
+/// Runs of u8 payloads are copied to a buffer and compared `LANES` at a time,
+/// which the compiler turns into vector instructions.
+pub fn functionality_batch(payloads: &[Payload]) -> Vec<Outcome> {
+    let mut outcomes = Vec::with_capacity(payloads.len());
+    let mut bytes = Vec::new();
+    let mut rest = payloads;
+    while let Some(first) = rest.first() {
+        bytes.clear();
+        bytes.extend(rest.iter().map_while(|payload| match payload {
+            Payload::U8(value) => Some(*value),
+            _ => None,
+        }));
+        if bytes.is_empty() {
+            outcomes.push(Outcome(first.is_zero()));
+            rest = &rest[1..];
+        } else {
+            zero_bytes(&bytes, &mut outcomes);
+            rest = &rest[bytes.len()..];
+        }
+    }
+    outcomes
+}
+
+fn zero_bytes(bytes: &[u8], outcomes: &mut Vec<Outcome>) {
+    let chunks = bytes.chunks_exact(LANES);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        let lanes: [u8; LANES] = chunk.try_into().unwrap();
+        outcomes.extend(lanes.map(|byte| Outcome(byte == 0)));
+    }
+    outcomes.extend(remainder.iter().map(|byte| Outcome(*byte == 0)));
+}
+
+/// Fallible provider functionality.
+/// The in-process computation cannot fail, but callers should code against
+/// this entry point so that failing providers can be swapped in.
+pub fn try_functionality(payload: Payload) -> Result<Outcome, ProviderError> {
+    Ok(functionality(payload))
+}
+
+/// Asynchronous provider functionality.
+/// Same contract as `try_functionality`, for callers running on an async runtime.
+pub async fn functionality_async(payload: Payload) -> Result<Outcome, ProviderError> {
+    try_functionality(payload)
+}
+
+/// Provider interface, for callers that want to swap or mock the provider
+/// instead of calling the free functions, which are the behavior of DefaultProvider.
+/// With the `mock` feature, `MockProvider` implements it with mockall.
+#[cfg_attr(feature = "mock", mockall::automock)]
+pub trait Provider {
+    fn functionality(&self, payload: Payload) -> Outcome;
+
+    fn evaluate(&self, payload: Payload, predicate: &Predicate) -> Outcome;
+
+    fn functionality_batch(&self, payloads: &[Payload]) -> Vec<Outcome> {
+        payloads
+            .iter()
+            .map(|payload| self.functionality(payload.clone()))
+            .collect()
+    }
+
+    fn try_functionality(&self, payload: Payload) -> Result<Outcome, ProviderError> {
+        Ok(self.functionality(payload))
+    }
+
+    fn try_evaluate(
+        &self,
+        payload: Payload,
+        predicate: &Predicate,
+    ) -> Result<Outcome, ProviderError> {
+        Ok(self.evaluate(payload, predicate))
+    }
+}
+
+/// The in-process provider.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultProvider;
+
+impl Provider for DefaultProvider {
+    fn functionality(&self, payload: Payload) -> Outcome {
+        functionality(payload)
+    }
+
+    fn evaluate(&self, payload: Payload, predicate: &Predicate) -> Outcome {
+        evaluate(payload, predicate)
+    }
+
+    fn functionality_batch(&self, payloads: &[Payload]) -> Vec<Outcome> {
+        functionality_batch(payloads)
+    }
+
+    fn try_functionality(&self, payload: Payload) -> Result<Outcome, ProviderError> {
+        try_functionality(payload)
+    }
+
+    fn try_evaluate(
+        &self,
+        payload: Payload,
+        predicate: &Predicate,
+    ) -> Result<Outcome, ProviderError> {
+        try_evaluate(payload, predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The batch agrees with `functionality` across runs of bytes longer
+    /// and shorter than `LANES`, broken by other payloads.
+    #[test]
+    fn batch_agrees_with_functionality() {
+        let mut payloads: Vec<Payload> = (0..40u8).map(|value| Payload::U8(value % 3)).collect();
+        payloads.insert(17, Payload::Bytes(vec![0, 0]));
+        payloads.insert(18, Payload::I64(-1));
+        payloads.push(Payload::U16(0));
+        payloads.extend((0..5u8).map(Payload::U8));
+        let expected: Vec<Outcome> = payloads.iter().cloned().map(functionality).collect();
+        assert_eq!(functionality_batch(&payloads), expected);
+        assert_eq!(functionality_batch(&[]), vec![]);
+    }
 }
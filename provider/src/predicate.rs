@@ -0,0 +1,180 @@
+use std::ops::Not;
+
+use crate::Payload;
+
+/// Provider question about a payload.
+/// Integers are compared by value, whatever their width and sign.
+/// A buffer matches when every one of its bytes matches, so the empty buffer always does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// The value equals this one.
+    Equals(i128),
+    /// The value lies between `min` and `max`, both included.
+    Range {
+        min: i128,
+        max: i128,
+    },
+    /// The bits selected by `mask` equal `value`.
+    /// Negative values are sign-extended to 64 bits in two's complement.
+    Bitmask {
+        mask: u64,
+        value: u64,
+    },
+    Even,
+    Odd,
+    /// The value is one of these.
+    In(Vec<i128>),
+    Not(Box<Predicate>),
+    /// Every predicate holds; true when there are none.
+    All(Vec<Predicate>),
+    /// Some predicate holds; false when there are none.
+    Any(Vec<Predicate>),
+}
+
+impl Predicate {
+    /// The predicate `functionality` evaluates.
+    pub fn zero() -> Self {
+        Predicate::Equals(0)
+    }
+
+    pub fn and(self, other: Predicate) -> Self {
+        Predicate::All(vec![self, other])
+    }
+
+    pub fn or(self, other: Predicate) -> Self {
+        Predicate::Any(vec![self, other])
+    }
+
+    pub fn matches(&self, payload: &Payload) -> bool {
+        match payload {
+            Payload::U8(value) => self.matches_integer((*value).into()),
+            Payload::U16(value) => self.matches_integer((*value).into()),
+            Payload::U32(value) => self.matches_integer((*value).into()),
+            Payload::U64(value) => self.matches_integer((*value).into()),
+            Payload::I8(value) => self.matches_integer((*value).into()),
+            Payload::I16(value) => self.matches_integer((*value).into()),
+            Payload::I32(value) => self.matches_integer((*value).into()),
+            Payload::I64(value) => self.matches_integer((*value).into()),
+            Payload::Bytes(bytes) => bytes
+                .iter()
+                .all(|byte| self.matches_integer((*byte).into())),
+        }
+    }
+
+    fn matches_integer(&self, value: i128) -> bool {
+        match self {
+            Predicate::Equals(expected) => value == *expected,
+            Predicate::Range { min, max } => (*min..=*max).contains(&value),
+            Predicate::Bitmask { mask, value: bits } => (value as u64) & mask == *bits,
+            Predicate::Even => value % 2 == 0,
+            Predicate::Odd => value % 2 != 0,
+            Predicate::In(values) => values.contains(&value),
+            Predicate::Not(predicate) => !predicate.matches_integer(value),
+            Predicate::All(predicates) => predicates.iter().all(|p| p.matches_integer(value)),
+            Predicate::Any(predicates) => predicates.iter().any(|p| p.matches_integer(value)),
+        }
+    }
+}
+
+impl Not for Predicate {
+    type Output = Predicate;
+
+    fn not(self) -> Self::Output {
+        Predicate::Not(Box::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payloads() -> Vec<Payload> {
+        vec![
+            Payload::U8(0),
+            Payload::U8(255),
+            Payload::U16(256),
+            Payload::U32(0),
+            Payload::U64(u64::MAX),
+            Payload::I8(-1),
+            Payload::I16(0),
+            Payload::I32(i32::MIN),
+            Payload::I64(7),
+            Payload::Bytes(vec![]),
+            Payload::Bytes(vec![0, 0]),
+            Payload::Bytes(vec![0, 1]),
+        ]
+    }
+
+    /// `functionality` is the zero predicate.
+    #[test]
+    fn zero_agrees_with_is_zero() {
+        for payload in payloads() {
+            assert_eq!(
+                Predicate::zero().matches(&payload),
+                payload.is_zero(),
+                "{:?}",
+                payload
+            );
+            assert_eq!(
+                crate::functionality(payload.clone()),
+                crate::evaluate(payload, &Predicate::zero())
+            );
+        }
+    }
+
+    /// Integers compare by value, whatever their width and sign.
+    #[test]
+    fn compares_integers_by_value() {
+        let seven = Predicate::Equals(7);
+        assert!(seven.matches(&Payload::U8(7)));
+        assert!(seven.matches(&Payload::I64(7)));
+        assert!(!seven.matches(&Payload::U16(263)));
+        let negative = Predicate::Range { min: -128, max: -1 };
+        assert!(negative.matches(&Payload::I8(-1)));
+        assert!(negative.matches(&Payload::I32(-128)));
+        assert!(!negative.matches(&Payload::U8(255)));
+        assert!(Predicate::Equals(u64::MAX.into()).matches(&Payload::U64(u64::MAX)));
+        assert!(Predicate::In(vec![-1, 300]).matches(&Payload::U16(300)));
+        assert!(Predicate::Odd.matches(&Payload::I16(-3)));
+        assert!(Predicate::Even.matches(&Payload::I16(-4)));
+    }
+
+    /// Negative values are sign-extended to 64 bits.
+    #[test]
+    fn masks_two_complement_bits() {
+        let high = Predicate::Bitmask {
+            mask: 1 << 63,
+            value: 1 << 63,
+        };
+        assert!(high.matches(&Payload::I8(-1)));
+        assert!(high.matches(&Payload::U64(u64::MAX)));
+        assert!(!high.matches(&Payload::U8(255)));
+        let low = Predicate::Bitmask {
+            mask: 0b1100,
+            value: 0b0100,
+        };
+        assert!(low.matches(&Payload::U32(0b0110)));
+        assert!(!low.matches(&Payload::U32(0b1000)));
+    }
+
+    /// Buffers match when every byte does.
+    #[test]
+    fn matches_every_byte_of_buffers() {
+        let small = Predicate::Range { min: 0, max: 9 };
+        assert!(small.matches(&Payload::Bytes(vec![])));
+        assert!(small.matches(&Payload::Bytes(vec![1, 9])));
+        assert!(!small.matches(&Payload::Bytes(vec![1, 10])));
+        assert!((!Predicate::zero()).matches(&Payload::Bytes(vec![])));
+    }
+
+    #[test]
+    fn combines_predicates() {
+        let payload = Payload::U8(3);
+        assert!(Predicate::Odd.and(Predicate::In(vec![3])).matches(&payload));
+        assert!(!Predicate::Odd.and(Predicate::Even).matches(&payload));
+        assert!(Predicate::Even.or(Predicate::Equals(3)).matches(&payload));
+        assert!(!(!Predicate::Odd).matches(&payload));
+        assert!(Predicate::All(vec![]).matches(&payload));
+        assert!(!Predicate::Any(vec![]).matches(&payload));
+    }
+}
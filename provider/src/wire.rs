@@ -0,0 +1,97 @@
+//! JSON bodies of the provider HTTP API, shared by servers and clients.
+//!
+//! - `POST /v1/functionality`: `{"payload":{"type":"u8","value":0}}`,
+//!   answered with `{"outcome":true}`.
+//! - `POST /v1/functionality/batch`: `{"payloads":[...]}`,
+//!   answered with `{"outcomes":[...]}`, in the same order.
+//!
+//! Failures are answered with `{"error":"..."}` and the status telling the ProviderError:
+//! 422 for InvalidPayload, 503 for Unavailable, 504 for Timeout and 500 for Internal.
+//! The error is the reason of InvalidPayload and Internal, as in the frame protocol,
+//! and describes the others. Its `kind` names the ProviderError for transports
+//! without statuses, e.g. `{"error":"odd","kind":"invalid_payload"}`.
+//! Unreadable bodies are answered with 400.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Outcome, Payload, ProviderError};
+
+pub const FUNCTIONALITY_PATH: &str = "/v1/functionality";
+pub const FUNCTIONALITY_BATCH_PATH: &str = "/v1/functionality/batch";
+pub const HEALTH_PATH: &str = "/health";
+pub const READY_PATH: &str = "/ready";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionalityRequest {
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionalityResponse {
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub payloads: Vec<Payload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub outcomes: Vec<Outcome>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Missing from failures that are not the provider's, such as unreadable bodies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ErrorKind>,
+}
+
+/// The variant of a ProviderError.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    InvalidPayload,
+    Unavailable,
+    Timeout,
+    Internal,
+}
+
+impl From<&ProviderError> for ErrorResponse {
+    fn from(error: &ProviderError) -> Self {
+        let (kind, error) = match error {
+            ProviderError::InvalidPayload(reason) => (ErrorKind::InvalidPayload, reason.clone()),
+            ProviderError::Unavailable => (ErrorKind::Unavailable, error.to_string()),
+            ProviderError::Timeout => (ErrorKind::Timeout, error.to_string()),
+            ProviderError::Internal(reason) => (ErrorKind::Internal, reason.clone()),
+        };
+        Self {
+            error,
+            kind: Some(kind),
+        }
+    }
+}
+
+impl From<ErrorResponse> for ProviderError {
+    /// Responses without a kind are read as rejected payloads.
+    fn from(response: ErrorResponse) -> Self {
+        match response.kind {
+            None | Some(ErrorKind::InvalidPayload) => ProviderError::InvalidPayload(response.error),
+            Some(ErrorKind::Unavailable) => ProviderError::Unavailable,
+            Some(ErrorKind::Timeout) => ProviderError::Timeout,
+            Some(ErrorKind::Internal) => ProviderError::Internal(response.error),
+        }
+    }
+}
+
+/// HTTP status answering the error.
+pub fn error_status(error: &ProviderError) -> u16 {
+    match error {
+        ProviderError::InvalidPayload(_) => 422,
+        ProviderError::Unavailable => 503,
+        ProviderError::Timeout => 504,
+        ProviderError::Internal(_) => 500,
+    }
+}
@@ -0,0 +1,119 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::sync::oneshot;
+
+/// Completes when the `BackgroundServer` running the server is dropped.
+#[derive(Debug)]
+pub struct Shutdown(oneshot::Receiver<()>);
+
+impl Future for Shutdown {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.0).poll(cx).map(|_| ())
+    }
+}
+
+/// A server running in-process, on its own runtime and thread, for blocking tests
+/// of the adapters that call it. On drop, the server is told to shut down and
+/// joined, so a test leaves no server behind and its errors fail the test.
+#[derive(Debug)]
+pub struct BackgroundServer {
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BackgroundServer {
+    /// Runs the future returned by `serve`, which must complete once `Shutdown` does.
+    /// `serve` is called on the runtime, e.g. to register listeners bound beforehand.
+    pub fn start<F, S>(serve: F) -> Self
+    where
+        F: FnOnce(Shutdown) -> S + Send + 'static,
+        S: Future<Output = io::Result<()>>,
+    {
+        let (shutdown, receiver) = oneshot::channel();
+        let thread = thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime
+                .block_on(async move { serve(Shutdown(receiver)).await })
+                .unwrap();
+        });
+        Self {
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        }
+    }
+
+    /// Serves on an ephemeral port of the loopback interface, returned with the server.
+    pub fn tcp<F, S>(serve: F) -> (SocketAddr, Self)
+    where
+        F: FnOnce(TcpListener, Shutdown) -> S + Send + 'static,
+        S: Future<Output = io::Result<()>>,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let server = Self::start(move |shutdown| async move {
+            serve(TcpListener::from_std(listener)?, shutdown).await
+        });
+        (address, server)
+    }
+
+    /// Serves on a Unix socket created at `path`, e.g. in a `TempDir`.
+    #[cfg(unix)]
+    pub fn uds<F, S>(path: &Path, serve: F) -> Self
+    where
+        F: FnOnce(UnixListener, Shutdown) -> S + Send + 'static,
+        S: Future<Output = io::Result<()>>,
+    {
+        let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        Self::start(move |shutdown| async move {
+            serve(UnixListener::from_std(listener)?, shutdown).await
+        })
+    }
+}
+
+impl Drop for BackgroundServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            if let Err(panic) = thread.join() {
+                if !thread::panicking() {
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn stops_the_server_on_drop() {
+        let (stopped, receiver) = mpsc::channel();
+        let server = BackgroundServer::start(move |shutdown| async move {
+            shutdown.await;
+            stopped.send(()).unwrap();
+            Ok(())
+        });
+        assert!(receiver.try_recv().is_err());
+        drop(server);
+        assert_eq!(receiver.try_recv(), Ok(()));
+    }
+}
@@ -0,0 +1,490 @@
+//! Helpers for tests of network adapters.
+//!
+//! `StubServer` is a local HTTP server on an ephemeral port. Tests declare the
+//! requests they expect and the responses to answer, and the server checks on
+//! drop that every expectation was met, like mockall's `times(n)` at the wire level:
+//!
+//! ```no_run
+//! use test_support::{Response, StubServer};
+//!
+//! let server = StubServer::start();
+//! server
+//!     .expect("POST", "/v1/functionality")
+//!     .json_body(r#"{"payload":{"type":"u8","value":0}}"#)
+//!     .times(1)
+//!     .respond(Response::json(200, r#"{"outcome":true}"#));
+//! // ... point the adapter at server.url() and call it once ...
+//! ```
+//!
+//! `TempDir` is a scratch directory removed on drop, e.g. to hold Unix sockets.
+//!
+//! `BackgroundServer` runs a real server in-process, e.g. `provider-server`,
+//! and shuts it down when dropped.
+//!
+//! `SlowProvider` is a provider whose calls take their time, for tests of
+//! calls that overlap.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+mod background;
+mod slow_provider;
+mod temp_dir;
+
+pub use background::{BackgroundServer, Shutdown};
+pub use slow_provider::SlowProvider;
+pub use temp_dir::TempDir;
+
+/// Canned response of an expectation.
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    delay: Duration,
+}
+
+impl Response {
+    /// Empty response with the given status.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: String::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// JSON response with the given status.
+    pub fn json(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            content_type: "application/json",
+            body: body.into(),
+            ..Self::status(status)
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Waits before answering, e.g. to trigger client timeouts.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// How the body of a request is matched.
+#[derive(Debug, Clone)]
+enum BodyMatcher {
+    /// Byte for byte.
+    Exact(String),
+    /// As JSON values, whatever the formatting and the order of the keys.
+    Json(serde_json::Value),
+}
+
+impl BodyMatcher {
+    fn matches(&self, body: &str) -> bool {
+        match self {
+            BodyMatcher::Exact(expected) => body == expected,
+            BodyMatcher::Json(expected) => {
+                serde_json::from_str::<serde_json::Value>(body).is_ok_and(|body| body == *expected)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Expectation {
+    method: String,
+    path: String,
+    body: Option<BodyMatcher>,
+    /// Expected number of calls; any number when None.
+    times: Option<usize>,
+    calls: usize,
+    response: Response,
+}
+
+impl Expectation {
+    fn matches(&self, request: &Request) -> bool {
+        self.method == request.method
+            && self.path == request.path
+            && self
+                .body
+                .as_ref()
+                .is_none_or(|body| body.matches(&request.body))
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.times.is_some_and(|times| self.calls >= times)
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)?;
+        match &self.body {
+            Some(BodyMatcher::Exact(body)) => write!(f, " with body {}", body)?,
+            Some(BodyMatcher::Json(body)) => write!(f, " with JSON {}", body)?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// A received request.
+#[derive(Debug, Clone)]
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} with body {:?}", self.method, self.path, self.body)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    expectations: Vec<Expectation>,
+    /// Requests no expectation matched, answered with 501.
+    unexpected: Vec<Request>,
+}
+
+/// Local HTTP server answering the declared expectations.
+/// Panics on drop when an expectation was not called the expected number
+/// of times, or when a request matched no expectation.
+pub struct StubServer {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl StubServer {
+    /// Starts a server on an ephemeral port of the loopback interface.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("cannot bind the stub server");
+        let address = listener.local_addr().expect("stub server has no address");
+        let state = Arc::new(Mutex::new(State::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server = Self {
+            address,
+            state: Arc::clone(&state),
+            stopped: Arc::clone(&stopped),
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let state = Arc::clone(&state);
+                // A broken connection only affects the request it carried.
+                thread::spawn(move || {
+                    let _ = serve_connection(stream, &state);
+                });
+            }
+        });
+        server
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Base URL of the server, e.g. `http://127.0.0.1:54321`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Declares a request the server expects; call `respond` to register it.
+    /// Expectations are matched in the order they were declared, skipping
+    /// those already called their expected number of times.
+    pub fn expect(&self, method: &str, path: &str) -> ExpectationBuilder<'_> {
+        ExpectationBuilder {
+            server: self,
+            method: method.to_string(),
+            path: path.to_string(),
+            body: None,
+            times: None,
+        }
+    }
+
+    /// Checks every expectation, and forgets them.
+    pub fn checkpoint(&self) -> Result<(), String> {
+        let mut state = self.lock();
+        let mut problems = Vec::new();
+        for expectation in &state.expectations {
+            if let Some(times) = expectation.times {
+                if expectation.calls != times {
+                    problems.push(format!(
+                        "expected {} to be called {} time(s), but it was called {} time(s)",
+                        expectation, times, expectation.calls
+                    ));
+                }
+            }
+        }
+        for request in &state.unexpected {
+            problems.push(format!("unexpected request {}", request));
+        }
+        state.expectations.clear();
+        state.unexpected.clear();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wakes the accept loop up, so that it sees the flag.
+        let _ = TcpStream::connect(self.address);
+        let result = self.checkpoint();
+        if let Err(problems) = result {
+            if !thread::panicking() {
+                panic!("stub server expectations not met:\n{}", problems);
+            }
+        }
+    }
+}
+
+/// Expectation being declared, see `StubServer::expect`.
+#[must_use = "the expectation is only registered by `respond`"]
+pub struct ExpectationBuilder<'a> {
+    server: &'a StubServer,
+    method: String,
+    path: String,
+    body: Option<BodyMatcher>,
+    times: Option<usize>,
+}
+
+impl ExpectationBuilder<'_> {
+    /// Only matches requests with exactly this body.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(BodyMatcher::Exact(body.into()));
+        self
+    }
+
+    /// Only matches requests whose body is the same JSON value.
+    /// Panics if `body` is not JSON.
+    pub fn json_body(mut self, body: &str) -> Self {
+        let body = serde_json::from_str(body).expect("expected body is not JSON");
+        self.body = Some(BodyMatcher::Json(body));
+        self
+    }
+
+    /// Expects exactly `times` matching requests.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    pub fn never(self) -> Self {
+        self.times(0)
+    }
+
+    pub fn respond(self, response: Response) {
+        self.server.lock().expectations.push(Expectation {
+            method: self.method,
+            path: self.path,
+            body: self.body,
+            times: self.times,
+            calls: 0,
+            response,
+        });
+    }
+}
+
+/// Answers the requests of a connection, until the client closes it.
+fn serve_connection(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    while let Some((request, keep_alive)) = read_request(&mut reader)? {
+        let response = {
+            let mut state = state.lock().unwrap_or_else(|error| error.into_inner());
+            respond(&mut state, request)
+        };
+        thread::sleep(response.delay);
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+            response.status,
+            reason(response.status),
+            response.content_type,
+            response.body.len(),
+            if keep_alive { "keep-alive" } else { "close" },
+            response.body
+        )?;
+        writer.flush()?;
+        if !keep_alive {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn respond(state: &mut State, request: Request) -> Response {
+    let position = state
+        .expectations
+        .iter()
+        .position(|expectation| expectation.matches(&request) && !expectation.is_saturated())
+        .or_else(|| {
+            state
+                .expectations
+                .iter()
+                .position(|expectation| expectation.matches(&request))
+        });
+    match position {
+        Some(position) => {
+            let expectation = &mut state.expectations[position];
+            expectation.calls += 1;
+            expectation.response.clone()
+        }
+        None => {
+            let body = format!("no expectation matches {}", request);
+            state.unexpected.push(request);
+            Response::status(501).body(body)
+        }
+    }
+}
+
+/// Reads a request, and whether the connection stays open after it.
+/// Returns None when the client closed the connection.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<(Request, bool)>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut content_length = 0;
+    let mut keep_alive = parts.next() != Some("HTTP/1.0");
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "invalid content length")
+                })?;
+            } else if name.eq_ignore_ascii_case("connection") {
+                keep_alive = !value.eq_ignore_ascii_case("close");
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body).into_owned();
+    Ok(Some((Request { method, path, body }, keep_alive)))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Sends one request, and returns the whole response.
+    fn send(server: &StubServer, method: &str, path: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn answers_expectations_in_order() {
+        let server = StubServer::start();
+        server
+            .expect("POST", "/echo")
+            .json_body(r#"{"a": 1, "b": 2}"#)
+            .times(1)
+            .respond(Response::json(200, "first"));
+        server
+            .expect("POST", "/echo")
+            .respond(Response::status(503).delay(Duration::from_millis(10)));
+        let first = send(&server, "POST", "/echo", r#"{"b":2,"a":1}"#);
+        assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(first.ends_with("\r\n\r\nfirst"));
+        let second = send(&server, "POST", "/echo", r#"{"b":2,"a":1}"#);
+        assert!(second.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert_eq!(server.checkpoint(), Ok(()));
+    }
+
+    #[test]
+    fn reports_unmet_expectations() {
+        let server = StubServer::start();
+        server
+            .expect("GET", "/health")
+            .times(2)
+            .respond(Response::status(200));
+        server
+            .expect("GET", "/ready")
+            .never()
+            .respond(Response::status(200));
+        send(&server, "GET", "/health", "");
+        assert!(send(&server, "GET", "/other", "").starts_with("HTTP/1.1 501"));
+        let problems = server.checkpoint().unwrap_err();
+        assert_eq!(
+            problems,
+            "expected GET /health to be called 2 time(s), but it was called 1 time(s)\n\
+             unexpected request GET /other with body \"\""
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected GET /health to be called 1 time(s)")]
+    fn panics_on_drop() {
+        let server = StubServer::start();
+        server
+            .expect("GET", "/health")
+            .times(1)
+            .respond(Response::status(200));
+    }
+}
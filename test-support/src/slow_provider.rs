@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use provider::{Outcome, Payload, Predicate, Provider};
+
+/// The default provider, sleeping before it answers.
+///
+/// mockall's mocks answer one call at a time, so tests showing that calls
+/// overlap need a provider like this one. It also counts the calls running
+/// at once, for tests of concurrency limits.
+#[derive(Debug)]
+pub struct SlowProvider {
+    delay: Duration,
+    /// Only this payload is slow when set.
+    slow: Option<Payload>,
+    running: AtomicUsize,
+    most_running: AtomicUsize,
+}
+
+impl SlowProvider {
+    /// Sleeps `delay` on every call.
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            slow: None,
+            running: AtomicUsize::new(0),
+            most_running: AtomicUsize::new(0),
+        }
+    }
+
+    /// Only sleeps on `payload`, and answers the others at once.
+    pub fn only(mut self, payload: Payload) -> Self {
+        self.slow = Some(payload);
+        self
+    }
+
+    /// The most calls that ran at the same time.
+    pub fn most_running(&self) -> usize {
+        self.most_running.load(Ordering::SeqCst)
+    }
+
+    fn sleep(&self, payload: &Payload) {
+        if self.slow.as_ref().is_some_and(|slow| slow != payload) {
+            return;
+        }
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.most_running.fetch_max(running, Ordering::SeqCst);
+        thread::sleep(self.delay);
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Provider for SlowProvider {
+    fn functionality(&self, payload: Payload) -> Outcome {
+        self.sleep(&payload);
+        provider::functionality(payload)
+    }
+
+    fn evaluate(&self, payload: Payload, predicate: &Predicate) -> Outcome {
+        self.sleep(&payload);
+        provider::evaluate(payload, predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn counts_overlapping_calls() {
+        let provider = Arc::new(SlowProvider::new(Duration::from_millis(200)));
+        let calls: Vec<_> = (0..3)
+            .map(|_| {
+                let provider = Arc::clone(&provider);
+                thread::spawn(move || provider.functionality(Payload::U8(0)))
+            })
+            .collect();
+        for call in calls {
+            assert_eq!(call.join().unwrap(), Outcome(true));
+        }
+        assert_eq!(provider.most_running(), 3);
+    }
+
+    #[test]
+    fn only_sleeps_on_the_slow_payload() {
+        let provider = SlowProvider::new(Duration::from_secs(10)).only(Payload::U8(0));
+        let start = Instant::now();
+        assert_eq!(provider.functionality(Payload::U8(1)), Outcome(false));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(provider.most_running(), 0);
+    }
+}